@@ -0,0 +1,15 @@
+#![no_main]
+
+use azure_cognitive_speech_services::message::AzureCognitiveSpeechServicesMessage;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+	let Ok(message) = AzureCognitiveSpeechServicesMessage::try_from(data) else {
+		return;
+	};
+	// Anything we can parse, we must be able to serialize and parse back to the same message.
+	if let Ok(serialized) = message.clone().serialize_binary() {
+		let reparsed = AzureCognitiveSpeechServicesMessage::try_from(serialized.as_slice()).expect("serialized message should parse");
+		assert_eq!(reparsed, message);
+	}
+});
@@ -0,0 +1,15 @@
+#![no_main]
+
+use azure_cognitive_speech_services::message::AzureCognitiveSpeechServicesMessage;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &str| {
+	let Ok(message) = data.parse::<AzureCognitiveSpeechServicesMessage>() else {
+		return;
+	};
+	// Anything we can parse, we must be able to serialize and parse back to the same message.
+	if let Ok(serialized) = message.clone().serialize_text() {
+		let reparsed: AzureCognitiveSpeechServicesMessage = serialized.parse().expect("serialized message should parse");
+		assert_eq!(reparsed, message);
+	}
+});
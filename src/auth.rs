@@ -0,0 +1,267 @@
+//! Credentials used to authenticate with the speech service.
+
+use std::{
+	fmt::Debug,
+	future::Future,
+	sync::Arc,
+	time::{Duration, Instant}
+};
+
+use futures_util::future::BoxFuture;
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Uri, header};
+use tokio::sync::Mutex;
+
+use crate::{AzureCloud, Error, transport::Proxy};
+
+/// A source of authentication headers for the speech service.
+///
+/// The synthesiser asks its credential for headers every time it opens a new connection, so implementations are free
+/// to rotate or refresh the credential between connections.
+pub trait Credential: Send + Sync + 'static {
+	/// Returns the headers to attach to the WebSocket upgrade request.
+	fn headers(&self) -> BoxFuture<'_, crate::Result<Vec<(HeaderName, HeaderValue)>>>;
+}
+
+impl<C: Credential + ?Sized> Credential for Arc<C> {
+	fn headers(&self) -> BoxFuture<'_, crate::Result<Vec<(HeaderName, HeaderValue)>>> {
+		(**self).headers()
+	}
+}
+
+const SUBSCRIPTION_KEY_HEADER: HeaderName = HeaderName::from_static("ocp-apim-subscription-key");
+
+fn bearer(token: &str) -> crate::Result<HeaderValue> {
+	let mut value = HeaderValue::try_from(format!("Bearer {token}"))?;
+	value.set_sensitive(true);
+	Ok(value)
+}
+
+/// Authenticates with a speech resource's subscription key via the `Ocp-Apim-Subscription-Key` header.
+#[derive(Clone)]
+pub struct SubscriptionKey(HeaderValue);
+
+impl SubscriptionKey {
+	pub fn new(key: impl AsRef<str>) -> crate::Result<Self> {
+		let mut key = HeaderValue::from_str(key.as_ref())?;
+		key.set_sensitive(true);
+		Ok(Self(key))
+	}
+}
+
+impl Debug for SubscriptionKey {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str("SubscriptionKey(..)")
+	}
+}
+
+impl Credential for SubscriptionKey {
+	fn headers(&self) -> BoxFuture<'_, crate::Result<Vec<(HeaderName, HeaderValue)>>> {
+		Box::pin(async move { Ok(vec![(SUBSCRIPTION_KEY_HEADER, self.0.clone())]) })
+	}
+}
+
+/// Authenticates with a fixed authorization token via the `Authorization: Bearer` header.
+///
+/// Tokens issued by the speech service's token endpoint are only valid for 10 minutes; to have them refreshed
+/// automatically, see [`IssuedToken`].
+#[derive(Clone)]
+pub struct AuthorizationToken(HeaderValue);
+
+impl AuthorizationToken {
+	pub fn new(token: impl AsRef<str>) -> crate::Result<Self> {
+		Ok(Self(bearer(token.as_ref())?))
+	}
+}
+
+impl Debug for AuthorizationToken {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str("AuthorizationToken(..)")
+	}
+}
+
+impl Credential for AuthorizationToken {
+	fn headers(&self) -> BoxFuture<'_, crate::Result<Vec<(HeaderName, HeaderValue)>>> {
+		Box::pin(async move { Ok(vec![(header::AUTHORIZATION, self.0.clone())]) })
+	}
+}
+
+/// Authenticates with authorization tokens exchanged for a subscription key at the speech service's `issueToken`
+/// endpoint.
+///
+/// Tokens are cached and shared between connections, and are refreshed shortly before their 10 minute lifetime runs
+/// out. This keeps the subscription key itself off of the WebSocket connection; the key is only ever sent to the token
+/// endpoint.
+pub struct IssuedToken {
+	endpoint: Uri,
+	key: HeaderValue,
+	refresh_after: Duration,
+	proxy: Option<Proxy>,
+	cached: Mutex<Option<(HeaderValue, Instant)>>
+}
+
+impl IssuedToken {
+	/// Issued tokens are valid for 10 minutes; refresh them a minute early so a connection never races expiry.
+	const DEFAULT_REFRESH_AFTER: Duration = Duration::from_secs(9 * 60);
+
+	/// Creates a credential which requests tokens from the `issueToken` endpoint of the given region in the global
+	/// Azure cloud. Use [`IssuedToken::for_cloud`] for resources in other clouds.
+	pub fn new(region: impl AsRef<str>, key: impl AsRef<str>) -> crate::Result<Self> {
+		Self::for_cloud(AzureCloud::Global, region, key)
+	}
+
+	/// Creates a credential which requests tokens from the `issueToken` endpoint of the given region in `cloud`.
+	pub fn for_cloud(cloud: AzureCloud, region: impl AsRef<str>, key: impl AsRef<str>) -> crate::Result<Self> {
+		Self::with_endpoint(cloud.token_endpoint(region.as_ref()), key)
+	}
+
+	/// Creates a credential which requests tokens from a custom `issueToken` endpoint, e.g. for a resource with a
+	/// custom domain.
+	pub fn with_endpoint(endpoint: impl AsRef<str>, key: impl AsRef<str>) -> crate::Result<Self> {
+		let mut key = HeaderValue::from_str(key.as_ref())?;
+		key.set_sensitive(true);
+		Ok(Self {
+			endpoint: endpoint.as_ref().parse().map_err(|e| Error::InvalidEndpoint(format!("{e}")))?,
+			key,
+			refresh_after: Self::DEFAULT_REFRESH_AFTER,
+			proxy: None,
+			cached: Mutex::new(None)
+		})
+	}
+
+	/// Configures how long an issued token is reused before a new one is requested.
+	pub fn with_refresh_after(mut self, refresh_after: Duration) -> Self {
+		self.refresh_after = refresh_after;
+		self
+	}
+
+	/// Configures a proxy to request tokens through.
+	pub fn with_proxy(mut self, proxy: Proxy) -> Self {
+		self.proxy = Some(proxy);
+		self
+	}
+
+	async fn issue(&self) -> crate::Result<HeaderValue> {
+		let mut headers = HeaderMap::new();
+		headers.insert(SUBSCRIPTION_KEY_HEADER, self.key.clone());
+		let response = crate::http::request(Method::POST, &self.endpoint, &headers, &[], self.proxy.as_ref()).await?;
+		let body = String::from_utf8_lossy(response.body());
+		if !response.status().is_success() {
+			return Err(Error::Credential(format!("token endpoint responded with {}: {}", response.status(), body.trim()).into()));
+		}
+		bearer(body.trim())
+	}
+}
+
+impl Debug for IssuedToken {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("IssuedToken")
+			.field("endpoint", &self.endpoint)
+			.field("refresh_after", &self.refresh_after)
+			.field("proxy", &self.proxy)
+			.finish_non_exhaustive()
+	}
+}
+
+impl Credential for IssuedToken {
+	fn headers(&self) -> BoxFuture<'_, crate::Result<Vec<(HeaderName, HeaderValue)>>> {
+		Box::pin(async move {
+			let mut cached = self.cached.lock().await;
+			let token = match cached.as_ref() {
+				Some((token, issued_at)) if issued_at.elapsed() < self.refresh_after => token.clone(),
+				_ => {
+					let issued_at = Instant::now();
+					let token = self.issue().await?;
+					*cached = Some((token.clone(), issued_at));
+					token
+				}
+			};
+			Ok(vec![(header::AUTHORIZATION, token)])
+		})
+	}
+}
+
+type TokenFuture = BoxFuture<'static, Result<String, Box<dyn std::error::Error + Send + Sync>>>;
+
+/// Authenticates with tokens obtained from a user-provided callback, e.g. Microsoft Entra ID access tokens from an
+/// identity library.
+///
+/// The callback is invoked for every new connection; it is responsible for caching tokens itself if need be.
+pub struct TokenCallback {
+	callback: Box<dyn Fn() -> TokenFuture + Send + Sync>
+}
+
+impl TokenCallback {
+	/// Creates a credential which sends the token returned by `callback` as-is.
+	pub fn new<F, Fut, E>(callback: F) -> Self
+	where
+		F: Fn() -> Fut + Send + Sync + 'static,
+		Fut: Future<Output = Result<String, E>> + Send + 'static,
+		E: Into<Box<dyn std::error::Error + Send + Sync>>
+	{
+		Self {
+			callback: Box::new(move || {
+				let fut = callback();
+				Box::pin(async move { fut.await.map_err(Into::into) })
+			})
+		}
+	}
+
+	/// Creates a credential for Microsoft Entra ID access tokens returned by `callback`.
+	///
+	/// The speech service expects Entra ID tokens to be qualified with the full resource ID of the speech resource
+	/// (`/subscriptions/.../providers/Microsoft.CognitiveServices/accounts/...`), so tokens are sent as
+	/// `aad#{resource_id}#{token}`.
+	pub fn entra_id<F, Fut, E>(resource_id: impl Into<String>, callback: F) -> Self
+	where
+		F: Fn() -> Fut + Send + Sync + 'static,
+		Fut: Future<Output = Result<String, E>> + Send + 'static,
+		E: Into<Box<dyn std::error::Error + Send + Sync>>
+	{
+		let resource_id: Arc<str> = resource_id.into().into();
+		Self::new(move || {
+			let resource_id = Arc::clone(&resource_id);
+			let fut = callback();
+			async move { fut.await.map(|token| format!("aad#{resource_id}#{token}")) }
+		})
+	}
+}
+
+impl Debug for TokenCallback {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str("TokenCallback(..)")
+	}
+}
+
+impl Credential for TokenCallback {
+	fn headers(&self) -> BoxFuture<'_, crate::Result<Vec<(HeaderName, HeaderValue)>>> {
+		Box::pin(async move {
+			let token = (self.callback)().await.map_err(Error::Credential)?;
+			Ok(vec![(header::AUTHORIZATION, bearer(&token)?)])
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_issued_token_endpoint() -> crate::Result<()> {
+		assert_eq!(IssuedToken::new("westus2", "key")?.endpoint, "https://westus2.api.cognitive.microsoft.com/sts/v1.0/issueToken");
+		assert_eq!(
+			IssuedToken::for_cloud(AzureCloud::UsGovernment, "usgovvirginia", "key")?.endpoint,
+			"https://usgovvirginia.api.cognitive.microsoft.us/sts/v1.0/issueToken"
+		);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_entra_id_token() -> crate::Result<()> {
+		let credential = TokenCallback::entra_id("/subscriptions/x/resourceGroups/y", || async { Ok::<_, std::io::Error>("token".to_string()) });
+		let headers = credential.headers().await?;
+		assert_eq!(headers.len(), 1);
+		assert_eq!(headers[0].0, header::AUTHORIZATION);
+		assert_eq!(headers[0].1, "Bearer aad#/subscriptions/x/resourceGroups/y#token");
+		Ok(())
+	}
+}
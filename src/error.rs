@@ -1,4 +1,6 @@
-use http::header::InvalidHeaderValue;
+use std::{fmt, time::Duration};
+
+use http::{StatusCode, header::InvalidHeaderValue};
 use thiserror::Error;
 
 use crate::message::AzureCognitiveSpeechServicesMessageError;
@@ -8,7 +10,7 @@ pub enum Error {
 	#[error("invalid key: {0}")]
 	InvalidKey(#[from] InvalidHeaderValue),
 	#[error("websocket error: {0}")]
-	Tungstenite(#[from] tokio_websockets::Error),
+	Tungstenite(tokio_websockets::Error),
 	#[error("I/O error: {0}")]
 	Io(#[from] std::io::Error),
 	#[error("error parsing/building message: {0}")]
@@ -17,14 +19,223 @@ pub enum Error {
 	Ssml(#[from] ssml::Error),
 	#[error("expected `{0}` event to have a binary body")]
 	ExpectedBinary(&'static str),
-	#[error("missing `{0}` field in {0}")]
+	#[error("missing `{0}` field in {1}")]
 	MissingField(&'static str, &'static str),
 	#[error("failed to deserialize: {0}")]
 	Deserialize(#[from] simd_json::Error),
 	#[error("unexpected multiple streams in request")]
 	UnexpectedMultipleStreams,
 	#[error("unsupported audio format")]
-	UnsupportedAudioFormat
+	UnsupportedAudioFormat,
+	#[error("unknown output format `{0}`")]
+	UnknownOutputFormat(String),
+	#[error("invalid endpoint: {0}")]
+	InvalidEndpoint(String),
+	#[error("invalid header: {0}")]
+	InvalidHeader(String),
+	#[error("no credential configured")]
+	MissingCredential,
+	#[error("failed to obtain credential: {0}")]
+	Credential(Box<dyn std::error::Error + Send + Sync>),
+	#[error("cannot request `{0}` visemes: the SSML has no `<voice>` and no voice is configured")]
+	VisemesWithoutVoice(crate::VisemeType),
+	#[error("invalid recording: {0}")]
+	InvalidRecording(String),
+	#[error("HTTP error: {0}")]
+	Http(String),
+	#[error("proxy error: {0}")]
+	Proxy(String),
+	#[error("request was throttled: {0}")]
+	Throttled(ServiceError),
+	#[error("authentication failed: {0}")]
+	Unauthorized(ServiceError),
+	#[error("service rejected the request: {0}")]
+	InvalidRequest(ServiceError),
+	#[error("service unavailable: {0}")]
+	ServiceUnavailable(ServiceError),
+	#[error("connection closed unexpectedly: {0}")]
+	ConnectionClosed(ServiceError),
+	#[error("timed out connecting to the service after {0:?}")]
+	ConnectTimeout(Duration),
+	#[error("timed out waiting for the first audio after {0:?}")]
+	FirstAudioTimeout(Duration),
+	#[error("no message received from the service for {0:?}")]
+	IdleTimeout(Duration),
+	#[error("turn did not complete within {0:?}")]
+	TurnTimeout(Duration)
+}
+
+impl Error {
+	/// Returns the details of the error reported by the speech service, if this error originated from the service.
+	pub fn service_error(&self) -> Option<&ServiceError> {
+		match self {
+			Self::Throttled(e) | Self::Unauthorized(e) | Self::InvalidRequest(e) | Self::ServiceUnavailable(e) | Self::ConnectionClosed(e) => Some(e),
+			_ => None
+		}
+	}
+
+	fn service_error_mut(&mut self) -> Option<&mut ServiceError> {
+		match self {
+			Self::Throttled(e) | Self::Unauthorized(e) | Self::InvalidRequest(e) | Self::ServiceUnavailable(e) | Self::ConnectionClosed(e) => Some(e),
+			_ => None
+		}
+	}
+
+	/// Returns whether the request that caused this error may succeed if it is retried.
+	///
+	/// This is the case for throttling, transient service errors, connection failures, and stalls. Errors caused by the
+	/// request itself (e.g. invalid SSML or an unknown voice), by invalid credentials, or by exceeding the overall turn
+	/// deadline are not retryable.
+	pub fn is_retryable(&self) -> bool {
+		match self {
+			Self::Io(_) | Self::Tungstenite(tokio_websockets::Error::Io(_) | tokio_websockets::Error::AlreadyClosed) => true,
+			Self::ConnectTimeout(_) | Self::FirstAudioTimeout(_) | Self::IdleTimeout(_) => true,
+			_ => self.service_error().is_some_and(|e| e.retryable)
+		}
+	}
+
+	/// Attaches the ID of the request this error occurred in, if it is a service error without one.
+	pub(crate) fn with_request_id(mut self, request_id: &str) -> Self {
+		if let Some(e) = self.service_error_mut() {
+			e.request_id.get_or_insert_with(|| request_id.to_string());
+		}
+		self
+	}
+}
+
+impl From<tokio_websockets::Error> for Error {
+	fn from(e: tokio_websockets::Error) -> Self {
+		match e {
+			tokio_websockets::Error::Upgrade(tokio_websockets::upgrade::Error::DidNotSwitchProtocols(status)) => {
+				let reason = StatusCode::from_u16(status)
+					.ok()
+					.and_then(|status| status.canonical_reason())
+					.unwrap_or_default();
+				ServiceError::new(ServiceErrorCode::Http(status), reason).into_error()
+			}
+			e => Self::Tungstenite(e)
+		}
+	}
+}
+
+/// The code of a [`ServiceError`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceErrorCode {
+	/// The service closed the WebSocket connection with this close code.
+	Close(u16),
+	/// The service rejected the WebSocket upgrade request with this HTTP status code.
+	Http(u16)
+}
+
+impl fmt::Display for ServiceErrorCode {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Close(code) => write!(f, "close code {code}"),
+			Self::Http(status) => write!(f, "HTTP {status}")
+		}
+	}
+}
+
+/// Details of an error reported by the speech service, either via a WebSocket close frame or a failed upgrade
+/// request.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ServiceError {
+	/// The ID of the request the error occurred in, if the error occurred during a request.
+	pub request_id: Option<String>,
+	pub code: ServiceErrorCode,
+	/// The reason given by the service, if any.
+	pub reason: String,
+	/// Whether the request may succeed if it is retried.
+	pub retryable: bool
+}
+
+impl ServiceError {
+	pub(crate) fn new(code: ServiceErrorCode, reason: impl Into<String>) -> Self {
+		let retryable = match code {
+			ServiceErrorCode::Http(status) => status == 408 || status == 429 || status >= 500,
+			ServiceErrorCode::Close(code) => !matches!(code, 1003 | 1007 | 1008 | 1009 | 4400 | 4401 | 4403)
+		};
+		Self {
+			request_id: None,
+			code,
+			reason: reason.into(),
+			retryable
+		}
+	}
+
+	pub(crate) fn with_request_id(mut self, request_id: impl Into<String>) -> Self {
+		self.request_id = Some(request_id.into());
+		self
+	}
+
+	/// Classifies this error into the matching [`Error`] variant.
+	pub(crate) fn into_error(self) -> Error {
+		match self.code {
+			ServiceErrorCode::Http(429) | ServiceErrorCode::Close(4429) => Error::Throttled(self),
+			ServiceErrorCode::Http(401 | 403) | ServiceErrorCode::Close(4401 | 4403) => Error::Unauthorized(self),
+			ServiceErrorCode::Http(400..=499) | ServiceErrorCode::Close(1003 | 1007 | 1008 | 1009 | 4400) => Error::InvalidRequest(self),
+			ServiceErrorCode::Http(_) | ServiceErrorCode::Close(1001 | 1011..=1014 | 4500..=4599) => Error::ServiceUnavailable(self),
+			ServiceErrorCode::Close(_) => Error::ConnectionClosed(self)
+		}
+	}
+}
+
+impl fmt::Display for ServiceError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.code)?;
+		if !self.reason.is_empty() {
+			write!(f, ": {}", self.reason)?;
+		}
+		if let Some(request_id) = &self.request_id {
+			write!(f, " (request ID {request_id})")?;
+		}
+		Ok(())
+	}
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod tests {
+	use speech_synthesis::UtteranceConfig;
+
+	use super::*;
+	use crate::testing::{self, MockServer, MockTurn};
+
+	#[test]
+	fn test_classify_service_errors() {
+		let e = ServiceError::new(ServiceErrorCode::Close(1007), "Invalid SSML")
+			.with_request_id("abc")
+			.into_error();
+		assert!(matches!(e, Error::InvalidRequest(_)));
+		assert!(!e.is_retryable());
+		assert_eq!(e.to_string(), "service rejected the request: close code 1007: Invalid SSML (request ID abc)");
+
+		let e = Error::from(tokio_websockets::Error::Upgrade(tokio_websockets::upgrade::Error::DidNotSwitchProtocols(429))).with_request_id("abc");
+		assert!(matches!(e, Error::Throttled(_)));
+		assert!(e.is_retryable());
+		assert_eq!(e.service_error().and_then(|e| e.request_id.as_deref()), Some("abc"));
+
+		assert!(matches!(ServiceError::new(ServiceErrorCode::Close(1011), "").into_error(), Error::ServiceUnavailable(e) if e.retryable));
+
+		assert!(Error::FirstAudioTimeout(Duration::from_secs(1)).is_retryable());
+		assert!(!Error::TurnTimeout(Duration::from_secs(1)).is_retryable());
+	}
+
+	#[tokio::test]
+	async fn test_mock_service_errors() -> crate::Result<()> {
+		let server = MockServer::start_with([MockTurn::new().with_turn_start().with_close(1007, "Invalid SSML")]).await?;
+		match testing::synthesise(&server.synthesiser_builder().build()?, &UtteranceConfig::default()).await {
+			Err(Error::InvalidRequest(e)) => {
+				assert_eq!(e.reason, "Invalid SSML");
+				assert_eq!(e.request_id.as_deref(), Some(&*server.requests()[0].request_id));
+			}
+			r => panic!("expected invalid request error, got {r:?}")
+		}
+
+		server.reject_next_connection(503);
+		assert!(matches!(testing::synthesise(&server.synthesiser_builder().build()?, &UtteranceConfig::default()).await, Err(Error::ServiceUnavailable(_))));
+		Ok(())
+	}
+}
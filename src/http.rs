@@ -0,0 +1,122 @@
+//! A minimal HTTP/1.1 client, just enough to talk to the token service without pulling in a full HTTP stack.
+
+use http::{HeaderMap, Method, Response, StatusCode, Uri, header};
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt},
+	net::TcpStream
+};
+use tokio_websockets::Connector;
+
+use crate::{Error, transport::Proxy};
+
+pub(crate) async fn request(method: Method, uri: &Uri, headers: &HeaderMap, body: &[u8], proxy: Option<&Proxy>) -> crate::Result<Response<Vec<u8>>> {
+	let host = uri.host().ok_or_else(|| Error::Http(format!("missing host in URI `{uri}`")))?;
+	let tls = match uri.scheme_str() {
+		Some("https") => true,
+		Some("http") => false,
+		_ => return Err(Error::Http(format!("unsupported scheme in URI `{uri}`")))
+	};
+	let port = uri.port_u16().unwrap_or(if tls { 443 } else { 80 });
+
+	let stream = match proxy.filter(|proxy| proxy.applies_to(host)) {
+		Some(proxy) => proxy.connect(host, port).await?,
+		None => TcpStream::connect((host.trim_start_matches('[').trim_end_matches(']'), port)).await?
+	};
+	let connector = if tls { Connector::new()? } else { Connector::Plain };
+	let mut stream = connector.wrap(host, stream).await?;
+
+	let mut request = format!(
+		"{method} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+		uri.path_and_query().map_or("/", |p| p.as_str()),
+		uri.authority().map_or(host, |a| a.as_str()),
+		body.len()
+	)
+	.into_bytes();
+	for (name, value) in headers {
+		request.extend_from_slice(name.as_str().as_bytes());
+		request.extend_from_slice(b": ");
+		request.extend_from_slice(value.as_bytes());
+		request.extend_from_slice(b"\r\n");
+	}
+	request.extend_from_slice(b"\r\n");
+	request.extend_from_slice(body);
+	stream.write_all(&request).await?;
+	stream.flush().await?;
+
+	let mut raw = Vec::new();
+	stream.read_to_end(&mut raw).await?;
+	parse_response(&raw)
+}
+
+fn parse_response(raw: &[u8]) -> crate::Result<Response<Vec<u8>>> {
+	let mut headers = [httparse::EMPTY_HEADER; 64];
+	let mut parsed = httparse::Response::new(&mut headers);
+	let header_len = match parsed.parse(raw) {
+		Ok(httparse::Status::Complete(len)) => len,
+		Ok(httparse::Status::Partial) => return Err(Error::Http("truncated response".to_string())),
+		Err(e) => return Err(Error::Http(format!("malformed response: {e}")))
+	};
+
+	let status = parsed
+		.code
+		.and_then(|code| StatusCode::from_u16(code).ok())
+		.ok_or_else(|| Error::Http("missing status code".to_string()))?;
+	let mut response = Response::builder().status(status);
+	for header in parsed.headers.iter() {
+		response = response.header(header.name, header.value);
+	}
+	let mut response = response.body(()).map_err(|e| Error::Http(e.to_string()))?;
+
+	let body = &raw[header_len..];
+	let body = if response
+		.headers()
+		.get(header::TRANSFER_ENCODING)
+		.is_some_and(|te| te.as_bytes().eq_ignore_ascii_case(b"chunked"))
+	{
+		decode_chunked(body)?
+	} else {
+		body.to_vec()
+	};
+	response.headers_mut().remove(header::TRANSFER_ENCODING);
+	Ok(response.map(|()| body))
+}
+
+fn decode_chunked(mut body: &[u8]) -> crate::Result<Vec<u8>> {
+	let mut decoded = Vec::with_capacity(body.len());
+	loop {
+		let (size, rest) = match httparse::parse_chunk_size(body) {
+			Ok(httparse::Status::Complete((consumed, size))) => (size, &body[consumed..]),
+			_ => return Err(Error::Http("malformed chunked body".to_string()))
+		};
+		if size == 0 {
+			return Ok(decoded);
+		}
+		// The chunk is followed by a CRLF.
+		let Some(chunk_len) = usize::try_from(size).ok().and_then(|size| size.checked_add(2)) else {
+			return Err(Error::Http(format!("chunk size {size} is too large")));
+		};
+		if rest.len() < chunk_len {
+			return Err(Error::Http("truncated chunked body".to_string()));
+		}
+		decoded.extend_from_slice(&rest[..chunk_len - 2]);
+		body = &rest[chunk_len..];
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parse_chunked_response() -> crate::Result<()> {
+		let response = parse_response(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n")?;
+		assert_eq!(response.status(), StatusCode::OK);
+		assert_eq!(response.body(), b"hello world");
+
+		assert!(matches!(
+			parse_response(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nffffffffffffffff\r\nhello\r\n0\r\n\r\n"),
+			Err(Error::Http(e)) if e.contains("too large")
+		));
+		Ok(())
+	}
+}
@@ -1,8 +1,19 @@
+pub mod auth;
 mod error;
+mod http;
 pub mod message;
+pub mod recording;
+pub mod subtitles;
 mod synthesiser;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod transport;
 
 pub use self::{
-	error::{Error, Result},
-	synthesiser::AzureCognitiveSpeechServicesSynthesiser
+	error::{Error, Result, ServiceError, ServiceErrorCode},
+	synthesiser::{
+		AzureCloud, AzureCognitiveSpeechServicesSynthesiser, AzureCognitiveSpeechServicesSynthesiserBuilder, AzureOutputFormat, AzureUtteranceConfig,
+		PoolConfig, RetryPolicy, SsmlTextMap, SvgVisemeAnimation, SynthesisEvent, SynthesisMetrics, SynthesisSession, Timeouts, VISEME_PHONEMES, VisemeType,
+		viseme_phonemes
+	}
 };
@@ -0,0 +1,619 @@
+use std::{
+	fmt::{Debug, Write},
+	str::{FromStr, Utf8Error},
+	time::{SystemTime, UNIX_EPOCH}
+};
+
+use bytes::Bytes;
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+use tokio_websockets::Message;
+use uuid::Uuid;
+
+mod payload;
+
+pub use self::payload::{
+	ApplicationContext, AudioMetadata, AudioMetadataEntry, BookmarkMetadata, BoundaryMetadata, BoundaryText, BoundaryType, MessagePayload, MetadataOptions,
+	OsContext, Response, ResponseAudio, ServiceContext, SessionEndMetadata, SpeechConfig, SpeechConfigContext, SynthesisAudioOptions, SynthesisContext,
+	SynthesisOptions, SystemContext, TurnEnd, TurnStart, VisemeMetadata
+};
+
+#[derive(Error, Debug)]
+pub enum AzureCognitiveSpeechServicesMessageError {
+	#[error("Cannot parse a binary message into JSON")]
+	ParseBinary,
+	#[error("Failed to parse JSON message: {0}")]
+	ParseJson(#[from] simd_json::Error),
+	#[error("Failed to serialize JSON message: {0}")]
+	SerializeJson(simd_json::Error),
+	#[error("Expected a `{expected}` message, got `{actual}`")]
+	UnexpectedPath { expected: &'static str, actual: String },
+	#[error("Couldn't build Azure Cognitive Speech Services message: {0}")]
+	Builder(&'static str),
+	#[error("Bad header format: `{0}`")]
+	BadHeader(String),
+	#[error("Missing required header '{0}'")]
+	MissingHeader(&'static str),
+	#[error("Malformed message header section")]
+	MalformedHeaderSection,
+	#[error("Invalid UTF-8 in binary message header section: {0}")]
+	HeaderUtf8(#[from] Utf8Error),
+	#[error("Binary message is truncated: expected at least {expected} bytes, got {actual}")]
+	Truncated { expected: usize, actual: usize },
+	#[error("Header section is too long for a binary message ({0} bytes)")]
+	HeaderTooLong(usize),
+	#[error("Expected a {expected} message body")]
+	BodyKindMismatch { expected: &'static str }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum AzureCognitiveSpeechServicesMessageBody {
+	/// A binary body. Bodies of parsed messages share the buffer of the frame they were parsed from.
+	Binary(Bytes),
+	Text(String)
+}
+
+impl AzureCognitiveSpeechServicesMessageBody {
+	pub fn as_binary(&self) -> Option<&[u8]> {
+		match self {
+			AzureCognitiveSpeechServicesMessageBody::Binary(b) => Some(b.as_ref()),
+			AzureCognitiveSpeechServicesMessageBody::Text(_) => None
+		}
+	}
+
+	pub fn as_text(&self) -> Option<&String> {
+		match self {
+			AzureCognitiveSpeechServicesMessageBody::Binary(_) => None,
+			AzureCognitiveSpeechServicesMessageBody::Text(t) => Some(t)
+		}
+	}
+
+	pub fn into_binary(self) -> Option<Bytes> {
+		match self {
+			AzureCognitiveSpeechServicesMessageBody::Binary(b) => Some(b),
+			AzureCognitiveSpeechServicesMessageBody::Text(_) => None
+		}
+	}
+
+	pub fn into_text(self) -> Option<String> {
+		match self {
+			AzureCognitiveSpeechServicesMessageBody::Binary(_) => None,
+			AzureCognitiveSpeechServicesMessageBody::Text(t) => Some(t)
+		}
+	}
+}
+
+impl Debug for AzureCognitiveSpeechServicesMessageBody {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Text(text) => {
+				f.write_char('"')?;
+				f.write_str(text)?;
+				f.write_char('"')
+			}
+			Self::Binary(_) => f.write_str("<binary>")
+		}
+	}
+}
+
+impl From<Vec<u8>> for AzureCognitiveSpeechServicesMessageBody {
+	fn from(value: Vec<u8>) -> Self {
+		Self::Binary(value.into())
+	}
+}
+
+impl From<Bytes> for AzureCognitiveSpeechServicesMessageBody {
+	fn from(value: Bytes) -> Self {
+		Self::Binary(value)
+	}
+}
+
+impl From<String> for AzureCognitiveSpeechServicesMessageBody {
+	fn from(value: String) -> Self {
+		Self::Text(value)
+	}
+}
+
+impl<'s> From<&'s str> for AzureCognitiveSpeechServicesMessageBody {
+	fn from(value: &'s str) -> Self {
+		Self::Text(value.to_owned())
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AzureCognitiveSpeechServicesMessage {
+	request_id: String,
+	path: String,
+	content_type: Option<String>,
+	stream_id: Option<String>,
+	timestamp: Option<String>,
+	extra_headers: Vec<(String, String)>,
+	body: AzureCognitiveSpeechServicesMessageBody
+}
+
+impl AzureCognitiveSpeechServicesMessage {
+	pub const CONTENT_TYPE_JSON: &'static str = "application/json";
+	pub const CONTENT_TYPE_SSML: &'static str = "application/ssml+xml";
+
+	pub fn builder(path: impl ToString, request_id: impl ToString) -> AzureCognitiveSpeechServicesMessageBuilder {
+		AzureCognitiveSpeechServicesMessageBuilder::new(path, request_id)
+	}
+
+	/// Creates a builder for a JSON message carrying `payload`, with the path & content type set accordingly.
+	pub fn from_payload<P: MessagePayload>(
+		payload: &P,
+		request_id: impl ToString
+	) -> Result<AzureCognitiveSpeechServicesMessageBuilder, AzureCognitiveSpeechServicesMessageError> {
+		let body = simd_json::to_string(payload).map_err(AzureCognitiveSpeechServicesMessageError::SerializeJson)?;
+		Ok(Self::builder(P::PATH, request_id)
+			.with_content_type(Self::CONTENT_TYPE_JSON)
+			.with_body(body))
+	}
+
+	pub fn gen_request_id() -> String {
+		Uuid::new_v4().simple().to_string()
+	}
+
+	pub fn path(&self) -> &str {
+		&self.path
+	}
+
+	pub fn request_id(&self) -> &str {
+		&self.request_id
+	}
+
+	pub fn content_type(&self) -> Option<&String> {
+		self.content_type.as_ref()
+	}
+
+	pub fn stream_id(&self) -> Option<&String> {
+		self.stream_id.as_ref()
+	}
+
+	/// Returns the message's `X-Timestamp` header. Messages built locally are timestamped when they are serialized, if
+	/// no timestamp was explicitly set.
+	pub fn timestamp(&self) -> Option<&String> {
+		self.timestamp.as_ref()
+	}
+
+	/// Returns any headers besides `Path`, `X-RequestId`, `X-Timestamp`, `Content-Type` & `X-StreamId`, in the order
+	/// they were added or received.
+	pub fn extra_headers(&self) -> &[(String, String)] {
+		&self.extra_headers
+	}
+
+	/// Returns all of the message's headers as `(name, value)` pairs, in the order they are serialized.
+	pub fn headers(&self) -> impl Iterator<Item = (&str, &str)> + '_ {
+		[
+			Some(("Path", &self.path)),
+			Some(("X-RequestId", &self.request_id)),
+			self.timestamp.as_ref().map(|v| ("X-Timestamp", v)),
+			self.content_type.as_ref().map(|v| ("Content-Type", v)),
+			self.stream_id.as_ref().map(|v| ("X-StreamId", v))
+		]
+		.into_iter()
+		.flatten()
+		.map(|(name, value)| (name, value.as_str()))
+		.chain(self.extra_headers.iter().map(|(name, value)| (name.as_str(), value.as_str())))
+	}
+
+	/// Returns the value of the header with the given name, which is matched case-insensitively.
+	pub fn header(&self, name: &str) -> Option<&str> {
+		self.headers()
+			.find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+			.map(|(_, value)| value)
+	}
+
+	/// Sets the `X-Timestamp` header to the current time, unless it is already set.
+	pub(crate) fn stamp(&mut self) {
+		self.timestamp.get_or_insert_with(|| format_timestamp(SystemTime::now()));
+	}
+
+	pub fn body(&self) -> &AzureCognitiveSpeechServicesMessageBody {
+		&self.body
+	}
+
+	pub fn into_body(self) -> AzureCognitiveSpeechServicesMessageBody {
+		self.body
+	}
+
+	pub fn serialize_text(self) -> Result<String, AzureCognitiveSpeechServicesMessageError> {
+		match self.serialize_inner()? {
+			(headers, AzureCognitiveSpeechServicesMessageBody::Text(body)) => Ok(format!("{headers}\r\n\r\n{body}")),
+			(_, AzureCognitiveSpeechServicesMessageBody::Binary(_)) => Err(AzureCognitiveSpeechServicesMessageError::BodyKindMismatch { expected: "text" })
+		}
+	}
+
+	pub fn serialize_binary(self) -> Result<Vec<u8>, AzureCognitiveSpeechServicesMessageError> {
+		match self.serialize_inner()? {
+			(headers, AzureCognitiveSpeechServicesMessageBody::Binary(body)) => {
+				let header_len = u16::try_from(headers.len()).map_err(|_| AzureCognitiveSpeechServicesMessageError::HeaderTooLong(headers.len()))?;
+				Ok([&header_len.to_be_bytes(), headers.as_bytes(), &body].concat())
+			}
+			(_, AzureCognitiveSpeechServicesMessageBody::Text(_)) => Err(AzureCognitiveSpeechServicesMessageError::BodyKindMismatch { expected: "binary" })
+		}
+	}
+
+	/// Serializes the header section. Headers are always written in the same order: `Path`, `X-RequestId`,
+	/// `X-Timestamp`, `Content-Type`, `X-StreamId`, then any extra headers in insertion order.
+	fn serialize_inner(self) -> Result<(String, AzureCognitiveSpeechServicesMessageBody), AzureCognitiveSpeechServicesMessageError> {
+		// Reject anything which would be read back as a different set of headers.
+		for (name, value) in self.headers() {
+			if name.is_empty() || name.contains([':', '\r', '\n']) || value.contains(['\r', '\n']) {
+				return Err(AzureCognitiveSpeechServicesMessageError::BadHeader(format!("{name}: {value}")));
+			}
+		}
+
+		let timestamp = self.timestamp.unwrap_or_else(|| format_timestamp(SystemTime::now()));
+		let mut headers = format!("Path: {}\r\nX-RequestId: {}\r\nX-Timestamp: {timestamp}", self.path, self.request_id);
+		if let Some(content_type) = self.content_type {
+			let _ = write!(headers, "\r\nContent-Type: {content_type}");
+		}
+		if let Some(stream_id) = self.stream_id {
+			let _ = write!(headers, "\r\nX-StreamId: {stream_id}");
+		}
+		for (name, value) in self.extra_headers {
+			let _ = write!(headers, "\r\n{name}: {value}");
+		}
+		Ok((headers, self.body))
+	}
+
+	pub fn into_websocket_message(self) -> Result<Message, AzureCognitiveSpeechServicesMessageError> {
+		Ok(if matches!(&self.body, AzureCognitiveSpeechServicesMessageBody::Binary(_)) {
+			Message::binary(Bytes::from(self.serialize_binary()?))
+		} else {
+			Message::text(self.serialize_text()?)
+		})
+	}
+
+	pub fn into_json<D: DeserializeOwned>(self) -> Result<D, AzureCognitiveSpeechServicesMessageError> {
+		let mut body = self
+			.into_body()
+			.into_text()
+			.ok_or(AzureCognitiveSpeechServicesMessageError::ParseBinary)?;
+		Ok(simd_json::from_slice(unsafe { body.as_bytes_mut() })?)
+	}
+
+	/// Parses the body as a `P`, after checking that this message has the path `P` is sent on.
+	pub fn into_payload<P: MessagePayload>(self) -> Result<P, AzureCognitiveSpeechServicesMessageError> {
+		if self.path != P::PATH {
+			return Err(AzureCognitiveSpeechServicesMessageError::UnexpectedPath { expected: P::PATH, actual: self.path });
+		}
+		self.into_json()
+	}
+
+	pub fn into_json_abstract(self) -> Result<simd_json::OwnedValue, AzureCognitiveSpeechServicesMessageError> {
+		let mut body = self
+			.into_body()
+			.into_text()
+			.ok_or(AzureCognitiveSpeechServicesMessageError::ParseBinary)?;
+		Ok(simd_json::to_owned_value(unsafe { body.as_bytes_mut() })?)
+	}
+}
+
+#[derive(Default, Clone)]
+pub struct AzureCognitiveSpeechServicesMessageBuilder {
+	request_id: Option<String>,
+	path: Option<String>,
+	content_type: Option<String>,
+	stream_id: Option<String>,
+	timestamp: Option<String>,
+	extra_headers: Vec<(String, String)>,
+	body: Option<AzureCognitiveSpeechServicesMessageBody>
+}
+
+impl AzureCognitiveSpeechServicesMessageBuilder {
+	pub fn new(path: impl ToString, request_id: impl ToString) -> Self {
+		Self {
+			path: Some(path.to_string()),
+			request_id: Some(request_id.to_string()),
+			..Default::default()
+		}
+	}
+
+	/// Creates a builder from `(name, value)` header pairs, as returned by
+	/// [`AzureCognitiveSpeechServicesMessage::headers`].
+	pub(crate) fn from_headers(mut headers: Vec<(String, String)>) -> Result<Self, AzureCognitiveSpeechServicesMessageError> {
+		// Header names are case-insensitive, but values are kept as-is; stream IDs & content type parameters may be
+		// case-sensitive.
+		let mut remove = |name: &str| {
+			headers
+				.iter()
+				.position(|(n, _)| n.eq_ignore_ascii_case(name))
+				.map(|i| headers.remove(i).1)
+		};
+
+		let mut builder = Self::new(
+			remove("Path").ok_or(AzureCognitiveSpeechServicesMessageError::MissingHeader("Path"))?,
+			remove("X-RequestId").ok_or(AzureCognitiveSpeechServicesMessageError::MissingHeader("X-RequestId"))?
+		);
+		builder.content_type = remove("Content-Type");
+		builder.stream_id = remove("X-StreamId");
+		builder.timestamp = remove("X-Timestamp");
+		builder.extra_headers = headers;
+		Ok(builder)
+	}
+
+	pub fn with_content_type(mut self, content_type: impl ToString) -> Self {
+		self.content_type = Some(content_type.to_string());
+		self
+	}
+
+	pub fn with_stream_id(mut self, stream_id: impl ToString) -> Self {
+		self.stream_id = Some(stream_id.to_string());
+		self
+	}
+
+	/// Sets the `X-Timestamp` header. If unset, the message is timestamped with the current time when serialized.
+	pub fn with_timestamp(mut self, timestamp: impl ToString) -> Self {
+		self.timestamp = Some(timestamp.to_string());
+		self
+	}
+
+	/// Adds an extra header, sent after the standard headers.
+	pub fn with_header(mut self, name: impl ToString, value: impl ToString) -> Self {
+		self.extra_headers.push((name.to_string(), value.to_string()));
+		self
+	}
+
+	pub fn with_body(mut self, body: impl Into<AzureCognitiveSpeechServicesMessageBody>) -> Self {
+		self.body = Some(body.into());
+		self
+	}
+
+	pub fn build(self) -> Result<AzureCognitiveSpeechServicesMessage, AzureCognitiveSpeechServicesMessageError> {
+		Ok(AzureCognitiveSpeechServicesMessage {
+			request_id: self
+				.request_id
+				.ok_or(AzureCognitiveSpeechServicesMessageError::Builder("missing request ID"))?,
+			path: self
+				.path
+				.ok_or(AzureCognitiveSpeechServicesMessageError::Builder("missing request path"))?,
+			content_type: self.content_type,
+			stream_id: self.stream_id,
+			timestamp: self.timestamp,
+			extra_headers: self.extra_headers,
+			body: self
+				.body
+				.ok_or(AzureCognitiveSpeechServicesMessageError::Builder("missing message body"))?
+		})
+	}
+}
+
+/// Formats a timestamp as ISO-8601 in UTC with millisecond precision, as the service expects for `X-Timestamp`, e.g.
+/// `2024-03-01T12:34:56.789Z`.
+fn format_timestamp(time: SystemTime) -> String {
+	let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+	let secs = since_epoch.as_secs();
+	// Convert days since the epoch to a civil date; see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+	let days = (secs / 86400) as i64 + 719468;
+	let era = days.div_euclid(146097);
+	let day_of_era = days.rem_euclid(146097);
+	let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let mp = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	let year = year_of_era + era * 400 + i64::from(month <= 2);
+	format!("{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z", secs / 3600 % 24, secs / 60 % 60, secs % 60, since_epoch.subsec_millis())
+}
+
+fn parse_headers(headers: impl AsRef<str>) -> Result<AzureCognitiveSpeechServicesMessageBuilder, AzureCognitiveSpeechServicesMessageError> {
+	let headers = headers
+		.as_ref()
+		.split("\r\n")
+		.filter(|c| !c.trim().is_empty())
+		.map(|c| {
+			let (header_name, header_value) = c
+				.split_once(':')
+				.ok_or_else(|| AzureCognitiveSpeechServicesMessageError::BadHeader(c.to_string()))?;
+			Ok((header_name.trim().to_string(), header_value.trim().to_string()))
+		})
+		.collect::<Result<Vec<_>, AzureCognitiveSpeechServicesMessageError>>()?;
+	AzureCognitiveSpeechServicesMessageBuilder::from_headers(headers)
+}
+
+impl FromStr for AzureCognitiveSpeechServicesMessage {
+	type Err = AzureCognitiveSpeechServicesMessageError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (headers, body) = s
+			.split_once("\r\n\r\n")
+			.ok_or(AzureCognitiveSpeechServicesMessageError::MalformedHeaderSection)?;
+		parse_headers(headers)?
+			.with_body(AzureCognitiveSpeechServicesMessageBody::Text(body.to_string()))
+			.build()
+	}
+}
+
+impl TryFrom<Bytes> for AzureCognitiveSpeechServicesMessage {
+	type Error = AzureCognitiveSpeechServicesMessageError;
+
+	/// Parses a binary message. The message body is a slice of `value`, so no audio is copied.
+	fn try_from(value: Bytes) -> Result<Self, AzureCognitiveSpeechServicesMessageError> {
+		const HEADER_LEN_SIZE: usize = std::mem::size_of::<u16>();
+		let truncated = |expected| AzureCognitiveSpeechServicesMessageError::Truncated { expected, actual: value.len() };
+		let header_len = match *value {
+			[hi, lo, ..] => u16::from_be_bytes([hi, lo]) as usize,
+			_ => return Err(truncated(HEADER_LEN_SIZE))
+		};
+		let headers = value
+			.get(HEADER_LEN_SIZE..HEADER_LEN_SIZE + header_len)
+			.ok_or_else(|| truncated(HEADER_LEN_SIZE + header_len))?;
+		let headers = std::str::from_utf8(headers)?;
+		let builder = parse_headers(headers)?;
+		builder
+			.with_body(AzureCognitiveSpeechServicesMessageBody::Binary(value.slice(HEADER_LEN_SIZE + header_len..)))
+			.build()
+	}
+}
+
+impl TryFrom<&[u8]> for AzureCognitiveSpeechServicesMessage {
+	type Error = AzureCognitiveSpeechServicesMessageError;
+
+	fn try_from(value: &[u8]) -> Result<Self, AzureCognitiveSpeechServicesMessageError> {
+		Self::try_from(Bytes::copy_from_slice(value))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use super::*;
+
+	fn random_token(rng: &mut fastrand::Rng, max_len: usize) -> String {
+		const CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-_.";
+		(0..rng.usize(1..=max_len)).map(|_| CHARS[rng.usize(..CHARS.len())] as char).collect()
+	}
+
+	fn random_message(rng: &mut fastrand::Rng) -> AzureCognitiveSpeechServicesMessage {
+		let mut builder = AzureCognitiveSpeechServicesMessage::builder(random_token(rng, 24), random_token(rng, 32))
+			.with_timestamp(format_timestamp(UNIX_EPOCH + Duration::from_millis(rng.u64(..1 << 42))));
+		if rng.bool() {
+			builder = builder.with_content_type(format!("{}/{}; charset={}", random_token(rng, 8), random_token(rng, 8), random_token(rng, 8)));
+		}
+		if rng.bool() {
+			builder = builder.with_stream_id(random_token(rng, 32));
+		}
+		for i in 0..rng.usize(..4) {
+			builder = builder.with_header(format!("X-Extra-{i}"), random_token(rng, 64));
+		}
+		let len = rng.usize(..1024);
+		builder
+			.with_body(if rng.bool() {
+				AzureCognitiveSpeechServicesMessageBody::Binary(std::iter::repeat_with(|| rng.u8(..)).take(len).collect())
+			} else {
+				AzureCognitiveSpeechServicesMessageBody::Text(std::iter::repeat_with(|| rng.char(..)).take(len).collect())
+			})
+			.build()
+			.unwrap()
+	}
+
+	#[test]
+	fn test_round_trip() -> Result<(), AzureCognitiveSpeechServicesMessageError> {
+		let mut rng = fastrand::Rng::with_seed(0x5eed);
+		for _ in 0..500 {
+			let message = random_message(&mut rng);
+			let parsed = match message.body() {
+				AzureCognitiveSpeechServicesMessageBody::Text(_) => message.clone().serialize_text()?.parse()?,
+				AzureCognitiveSpeechServicesMessageBody::Binary(_) => {
+					let serialized = message.clone().serialize_binary()?;
+					// Every truncation of the frame must be rejected (or parsed as a shorter body) without panicking.
+					for len in 0..serialized.len() {
+						let _ = AzureCognitiveSpeechServicesMessage::try_from(&serialized[..len]);
+					}
+					AzureCognitiveSpeechServicesMessage::try_from(serialized.as_slice())?
+				}
+			};
+			assert_eq!(parsed, message);
+		}
+		Ok(())
+	}
+
+	#[test]
+	fn test_malformed() {
+		assert!(matches!(
+			AzureCognitiveSpeechServicesMessage::try_from(&[0][..]),
+			Err(AzureCognitiveSpeechServicesMessageError::Truncated { expected: 2, actual: 1 })
+		));
+		assert!(matches!(
+			AzureCognitiveSpeechServicesMessage::try_from(&[0, 10, b'P'][..]),
+			Err(AzureCognitiveSpeechServicesMessageError::Truncated { expected: 12, actual: 3 })
+		));
+
+		let message = || AzureCognitiveSpeechServicesMessage::builder("audio", "abc");
+		assert!(matches!(
+			message().with_body("text").build().unwrap().serialize_binary(),
+			Err(AzureCognitiveSpeechServicesMessageError::BodyKindMismatch { expected: "binary" })
+		));
+		assert!(matches!(
+			message().with_body(vec![0]).build().unwrap().serialize_text(),
+			Err(AzureCognitiveSpeechServicesMessageError::BodyKindMismatch { expected: "text" })
+		));
+		assert!(matches!(
+			message()
+				.with_header("X-Long", "a".repeat(u16::MAX as usize))
+				.with_body(vec![0])
+				.build()
+				.unwrap()
+				.serialize_binary(),
+			Err(AzureCognitiveSpeechServicesMessageError::HeaderTooLong(_))
+		));
+		assert!(matches!(
+			message()
+				.with_header("X-Injected", "a\r\nPath: ssml")
+				.with_body("text")
+				.build()
+				.unwrap()
+				.serialize_text(),
+			Err(AzureCognitiveSpeechServicesMessageError::BadHeader(_))
+		));
+	}
+
+	#[test]
+	fn test_format_timestamp() {
+		assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+		assert_eq!(format_timestamp(UNIX_EPOCH + Duration::from_millis(951_827_696_789)), "2000-02-29T12:34:56.789Z");
+		assert_eq!(format_timestamp(UNIX_EPOCH + Duration::from_secs(4_102_444_799)), "2099-12-31T23:59:59.000Z");
+	}
+
+	#[test]
+	fn test_serialize_header_order() -> Result<(), AzureCognitiveSpeechServicesMessageError> {
+		let message = AzureCognitiveSpeechServicesMessage::builder("ssml", "abc")
+			.with_header("X-Custom", "1")
+			.with_content_type(AzureCognitiveSpeechServicesMessage::CONTENT_TYPE_SSML)
+			.with_timestamp("2024-03-01T12:34:56.789Z")
+			.with_header("X-Other", "2")
+			.with_body("<speak/>")
+			.build()?;
+		assert_eq!(
+			message.clone().serialize_text()?,
+			"Path: ssml\r\nX-RequestId: abc\r\nX-Timestamp: 2024-03-01T12:34:56.789Z\r\nContent-Type: application/ssml+xml\r\nX-Custom: 1\r\nX-Other: 2\r\n\r\n<speak/>"
+		);
+
+		let parsed: AzureCognitiveSpeechServicesMessage = message.serialize_text()?.parse()?;
+		assert_eq!(parsed.extra_headers(), [("X-Custom".to_string(), "1".to_string()), ("X-Other".to_string(), "2".to_string())]);
+		assert_eq!(parsed.timestamp().map(String::as_str), Some("2024-03-01T12:34:56.789Z"));
+
+		let message = AzureCognitiveSpeechServicesMessage::builder("audio", "abc")
+			.with_body(vec![1, 2, 3])
+			.build()?;
+		let serialized = message.serialize_binary()?;
+		let headers = std::str::from_utf8(&serialized[2..serialized.len() - 3]).unwrap();
+		assert!(headers.starts_with("Path: audio\r\nX-RequestId: abc\r\nX-Timestamp: "));
+		assert!(headers.ends_with('Z'));
+		Ok(())
+	}
+
+	#[test]
+	fn test_parse_binary_zero_copy() -> Result<(), AzureCognitiveSpeechServicesMessageError> {
+		let frame = Bytes::from(
+			AzureCognitiveSpeechServicesMessage::builder("audio", "abc")
+				.with_body(vec![1, 2, 3, 4])
+				.build()?
+				.serialize_binary()?
+		);
+		let message = AzureCognitiveSpeechServicesMessage::try_from(frame.clone())?;
+		let body = message.into_body().into_binary().unwrap();
+		assert_eq!(&body[..], [1, 2, 3, 4]);
+		assert_eq!(body.as_ptr(), frame[frame.len() - 4..].as_ptr());
+		Ok(())
+	}
+
+	#[test]
+	fn test_parse_headers() -> Result<(), AzureCognitiveSpeechServicesMessageError> {
+		let message: AzureCognitiveSpeechServicesMessage =
+			"path:audio.metadata\r\nx-requestid:ABC123\r\nContent-Type: application/json; charset=UTF-8\r\nX-StreamId: Ab12\r\nX-Vendor: Mixed Case\r\n\r\n{}"
+				.parse()?;
+		assert_eq!(message.path(), "audio.metadata");
+		assert_eq!(message.request_id(), "ABC123");
+		assert_eq!(message.content_type().map(String::as_str), Some("application/json; charset=UTF-8"));
+		assert_eq!(message.stream_id().map(String::as_str), Some("Ab12"));
+		assert_eq!(message.header("x-vendor"), Some("Mixed Case"));
+		assert_eq!(message.header("X-REQUESTID"), Some("ABC123"));
+		assert_eq!(message.header("X-Timestamp"), None);
+		assert_eq!(message.headers().map(|(name, _)| name).collect::<Vec<_>>(), ["Path", "X-RequestId", "Content-Type", "X-StreamId", "X-Vendor"]);
+		Ok(())
+	}
+}
@@ -0,0 +1,408 @@
+//! Typed bodies of the JSON messages exchanged with the speech service.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::DeserializeOwned};
+use simd_json::OwnedValue;
+
+/// A JSON message body with a known [`Path`](super::AzureCognitiveSpeechServicesMessage::path).
+///
+/// See [`AzureCognitiveSpeechServicesMessage::from_payload`](super::AzureCognitiveSpeechServicesMessage::from_payload)
+/// & [`AzureCognitiveSpeechServicesMessage::into_payload`](super::AzureCognitiveSpeechServicesMessage::into_payload).
+pub trait MessagePayload: Serialize + DeserializeOwned {
+	/// The path of messages carrying this payload.
+	const PATH: &'static str;
+}
+
+/// The body of the `speech.config` message, sent once when a connection is opened.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpeechConfig {
+	pub context: SpeechConfigContext
+}
+
+impl MessagePayload for SpeechConfig {
+	const PATH: &'static str = "speech.config";
+}
+
+/// Describes the client to the service, which shows up in Azure-side diagnostics.
+///
+/// The [`Default`] context describes this crate and the operating system it is running on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpeechConfigContext {
+	pub system: SystemContext,
+	pub os: OsContext,
+	/// The application using the client, if configured with [`SpeechConfigContext::with_application`].
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub application: Option<ApplicationContext>
+}
+
+impl SpeechConfigContext {
+	/// Identifies the application using the client, e.g. to help find its connections in support tickets.
+	pub fn with_application(mut self, name: impl Into<String>, version: impl Into<String>) -> Self {
+		self.application = Some(ApplicationContext {
+			name: name.into(),
+			version: version.into()
+		});
+		self
+	}
+}
+
+impl Default for SpeechConfigContext {
+	fn default() -> Self {
+		Self {
+			system: SystemContext {
+				name: env!("CARGO_PKG_NAME").to_string(),
+				version: env!("CARGO_PKG_VERSION").to_string(),
+				build: format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH)
+			},
+			os: OsContext::detect(),
+			application: None
+		}
+	}
+}
+
+/// The client library connecting to the service.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SystemContext {
+	pub name: String,
+	pub version: String,
+	pub build: String
+}
+
+/// The operating system the client is running on.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OsContext {
+	pub platform: String,
+	pub name: String,
+	pub version: String
+}
+
+impl OsContext {
+	fn detect() -> Self {
+		let platform = match std::env::consts::OS {
+			"linux" => "Linux",
+			"windows" => "Windows",
+			"macos" => "macOS",
+			"ios" => "iOS",
+			"android" => "Android",
+			"freebsd" => "FreeBSD",
+			os => os
+		}
+		.to_string();
+
+		// Linux distributions describe themselves in `os-release`; elsewhere, there's no portable way to get a version
+		// without calling into platform APIs.
+		let os_release = std::fs::read_to_string("/etc/os-release").unwrap_or_default();
+		let os_release = |key: &str| {
+			os_release.lines().find_map(|line| {
+				let value = line.strip_prefix(key)?.strip_prefix('=')?;
+				Some(value.trim_matches('"').to_string())
+			})
+		};
+		Self {
+			name: os_release("NAME").unwrap_or_else(|| platform.clone()),
+			version: os_release("VERSION_ID").unwrap_or_else(|| "unknown".to_string()),
+			platform
+		}
+	}
+}
+
+/// The application using the client.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ApplicationContext {
+	pub name: String,
+	pub version: String
+}
+
+/// The body of the `synthesis.context` message, sent before the `ssml` of each turn.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SynthesisContext {
+	pub synthesis: SynthesisOptions
+}
+
+impl MessagePayload for SynthesisContext {
+	const PATH: &'static str = "synthesis.context";
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SynthesisOptions {
+	pub audio: SynthesisAudioOptions
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SynthesisAudioOptions {
+	pub metadata_options: MetadataOptions,
+	/// The name of the output format, e.g. `riff-24khz-16bit-mono-pcm`.
+	pub output_format: String
+}
+
+/// Which `audio.metadata` entries the service should send.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MetadataOptions {
+	pub bookmark_enabled: bool,
+	pub punctuation_boundary_enabled: bool,
+	pub sentence_boundary_enabled: bool,
+	pub session_end_enabled: bool,
+	pub viseme_enabled: bool,
+	pub word_boundary_enabled: bool
+}
+
+/// Identifies the service instance handling a turn.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceContext {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub service_tag: Option<String>
+}
+
+/// The body of the `turn.start` message, sent by the service when it begins processing a turn.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TurnStart {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub context: Option<ServiceContext>
+}
+
+impl MessagePayload for TurnStart {
+	const PATH: &'static str = "turn.start";
+}
+
+/// The body of the `response` message, which announces the audio stream of a turn.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub context: Option<ServiceContext>,
+	pub audio: ResponseAudio
+}
+
+impl MessagePayload for Response {
+	const PATH: &'static str = "response";
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseAudio {
+	/// How the audio is delivered; always `inline`, i.e. as `audio` messages on the same connection.
+	#[serde(rename = "type")]
+	pub kind: String,
+	/// The ID of the audio stream, matching the `X-StreamId` header of the `audio` messages.
+	pub stream_id: String
+}
+
+/// The body of the `turn.end` message, sent by the service once a turn is complete.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TurnEnd {}
+
+impl MessagePayload for TurnEnd {
+	const PATH: &'static str = "turn.end";
+}
+
+/// The body of an `audio.metadata` message.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AudioMetadata {
+	#[serde(rename = "Metadata")]
+	pub entries: Vec<AudioMetadataEntry>
+}
+
+impl MessagePayload for AudioMetadata {
+	const PATH: &'static str = "audio.metadata";
+}
+
+/// A single entry of an `audio.metadata` message. All offsets & durations are in ticks of 100 nanoseconds, relative
+/// to the beginning of the audio stream.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AudioMetadataEntry {
+	WordBoundary(BoundaryMetadata),
+	PunctuationBoundary(BoundaryMetadata),
+	SentenceBoundary(BoundaryMetadata),
+	Bookmark(BookmarkMetadata),
+	Viseme(VisemeMetadata),
+	SessionEnd(SessionEndMetadata),
+	/// An entry of a type this crate doesn't know about.
+	Unknown {
+		/// The `Type` of the entry.
+		kind: String,
+		/// The `Data` of the entry.
+		data: OwnedValue
+	}
+}
+
+impl AudioMetadataEntry {
+	/// Returns the `Type` of the entry, as sent by the service.
+	pub fn kind(&self) -> &str {
+		match self {
+			Self::WordBoundary(_) => "WordBoundary",
+			Self::PunctuationBoundary(_) => "PunctuationBoundary",
+			Self::SentenceBoundary(_) => "SentenceBoundary",
+			Self::Bookmark(_) => "Bookmark",
+			Self::Viseme(_) => "Viseme",
+			Self::SessionEnd(_) => "SessionEnd",
+			Self::Unknown { kind, .. } => kind
+		}
+	}
+}
+
+/// The wire representation of an [`AudioMetadataEntry`], before its `Data` is interpreted according to its `Type`.
+#[derive(Serialize, Deserialize)]
+struct RawAudioMetadataEntry {
+	#[serde(rename = "Type")]
+	kind: String,
+	#[serde(rename = "Data", default)]
+	data: OwnedValue
+}
+
+impl Serialize for AudioMetadataEntry {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		use serde::ser::Error;
+
+		let data = match self {
+			Self::WordBoundary(data) | Self::PunctuationBoundary(data) | Self::SentenceBoundary(data) => simd_json::serde::to_owned_value(data),
+			Self::Bookmark(data) => simd_json::serde::to_owned_value(data),
+			Self::Viseme(data) => simd_json::serde::to_owned_value(data),
+			Self::SessionEnd(data) => simd_json::serde::to_owned_value(data),
+			Self::Unknown { data, .. } => Ok(data.clone())
+		}
+		.map_err(S::Error::custom)?;
+		RawAudioMetadataEntry { kind: self.kind().to_string(), data }.serialize(serializer)
+	}
+}
+
+impl<'de> Deserialize<'de> for AudioMetadataEntry {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		fn data<T: DeserializeOwned, E: serde::de::Error>(data: OwnedValue) -> Result<T, E> {
+			simd_json::serde::from_owned_value(data).map_err(E::custom)
+		}
+
+		let RawAudioMetadataEntry { kind, data: raw } = RawAudioMetadataEntry::deserialize(deserializer)?;
+		Ok(match kind.as_str() {
+			"WordBoundary" => Self::WordBoundary(data(raw)?),
+			"PunctuationBoundary" => Self::PunctuationBoundary(data(raw)?),
+			"SentenceBoundary" => Self::SentenceBoundary(data(raw)?),
+			"Bookmark" => Self::Bookmark(data(raw)?),
+			"Viseme" => Self::Viseme(data(raw)?),
+			"SessionEnd" => Self::SessionEnd(data(raw)?),
+			_ => Self::Unknown { kind, data: raw }
+		})
+	}
+}
+
+/// The data of a word, punctuation, or sentence boundary.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BoundaryMetadata {
+	#[serde(rename = "Offset")]
+	pub offset: u64,
+	#[serde(rename = "Duration")]
+	pub duration: u64,
+	/// The offset of the text in the submitted SSML, in UTF-16 code units, if sent by the service.
+	#[serde(rename = "TextOffset", default, skip_serializing_if = "Option::is_none")]
+	pub text_offset: Option<u32>,
+	pub text: BoundaryText
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BoundaryText {
+	/// The text of the word, punctuation, or sentence.
+	#[serde(rename = "Text")]
+	pub text: String,
+	/// The length of the text in the submitted input.
+	#[serde(rename = "Length", default, skip_serializing_if = "Option::is_none")]
+	pub length: Option<u32>,
+	/// The kind of boundary. Punctuation & sentence boundaries may be sent as `WordBoundary` entries with a more
+	/// specific boundary type.
+	#[serde(rename = "BoundaryType", default, skip_serializing_if = "Option::is_none")]
+	pub boundary_type: Option<BoundaryType>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BoundaryType {
+	#[serde(rename = "WordBoundary")]
+	Word,
+	#[serde(rename = "PunctuationBoundary")]
+	Punctuation,
+	#[serde(rename = "SentenceBoundary")]
+	Sentence,
+	/// A boundary type this crate doesn't know about.
+	#[serde(other)]
+	Unknown
+}
+
+/// The data of an SSML `<bookmark>` being reached.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BookmarkMetadata {
+	#[serde(rename = "Offset")]
+	pub offset: u64,
+	/// The name of the bookmark.
+	#[serde(rename = "Bookmark")]
+	pub bookmark: String
+}
+
+/// The data of a viseme.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VisemeMetadata {
+	#[serde(rename = "Offset")]
+	pub offset: u64,
+	/// The Azure viseme ID, from `0` to `21`.
+	#[serde(rename = "VisemeId", default)]
+	pub viseme_id: u8,
+	#[serde(rename = "IsLastAnimation", default, skip_serializing_if = "Option::is_none")]
+	pub is_last_animation: Option<bool>,
+	/// A JSON-encoded chunk of animation frames, if an animation was requested via SSML.
+	#[serde(rename = "AnimationChunk", default, skip_serializing_if = "Option::is_none")]
+	pub animation_chunk: Option<String>
+}
+
+/// The data of the end of the synthesis session.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionEndMetadata {
+	/// The total duration of the audio.
+	#[serde(rename = "Offset")]
+	pub offset: u64
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::message::{AzureCognitiveSpeechServicesMessage, AzureCognitiveSpeechServicesMessageError};
+
+	#[test]
+	fn test_audio_metadata() -> Result<(), AzureCognitiveSpeechServicesMessageError> {
+		let message: AzureCognitiveSpeechServicesMessage = concat!(
+			"Path:audio.metadata\r\nX-RequestId:abc\r\nContent-Type:application/json\r\n\r\n",
+			r#"{"Metadata":[{"Type":"WordBoundary","Data":{"Offset":1000000,"Duration":2500000,"text":{"Text":"Hello","Length":5,"BoundaryType":"WordBoundary"}}},"#,
+			r#"{"Type":"Bookmark","Data":{"Offset":3500000,"Bookmark":"mark"}},{"Type":"TalkingAvatarSignal","Data":{"Offset":0}}]}"#
+		)
+		.parse()?;
+		let metadata = message.into_payload::<AudioMetadata>()?;
+		assert_eq!(
+			metadata.entries[..2],
+			[
+				AudioMetadataEntry::WordBoundary(BoundaryMetadata {
+					offset: 1_000_000,
+					duration: 2_500_000,
+					text_offset: None,
+					text: BoundaryText {
+						text: "Hello".to_string(),
+						length: Some(5),
+						boundary_type: Some(BoundaryType::Word)
+					}
+				}),
+				AudioMetadataEntry::Bookmark(BookmarkMetadata {
+					offset: 3_500_000,
+					bookmark: "mark".to_string()
+				})
+			]
+		);
+		assert_eq!(metadata.entries[2].kind(), "TalkingAvatarSignal");
+
+		let message = AzureCognitiveSpeechServicesMessage::from_payload(&metadata, "abc")?.build()?;
+		assert_eq!(message.path(), "audio.metadata");
+		assert_eq!(message.into_payload::<AudioMetadata>()?, metadata);
+		Ok(())
+	}
+
+	#[test]
+	fn test_unexpected_path() -> Result<(), AzureCognitiveSpeechServicesMessageError> {
+		let message = AzureCognitiveSpeechServicesMessage::from_payload(&TurnEnd {}, "abc")?.build()?;
+		assert!(matches!(message.into_payload::<TurnStart>(), Err(AzureCognitiveSpeechServicesMessageError::UnexpectedPath { expected: "turn.start", .. })));
+		Ok(())
+	}
+}
@@ -0,0 +1,597 @@
+//! Recording & replay of the messages exchanged with the speech service.
+//!
+//! A [`Recorder`] attached to a synthesiser with
+//! [`with_recorder`](crate::AzureCognitiveSpeechServicesSynthesiserBuilder::with_recorder) writes every message sent
+//! and received to a file as JSON lines, one message per line:
+//!
+//! ```text
+//! {"time":1700000000000,"direction":"sent","requestId":"...","headers":[["Path","ssml"],["X-RequestId","..."],["X-Timestamp","..."],["Content-Type","application/ssml+xml"]],"text":"<speak ...>"}
+//! {"time":1700000000123,"direction":"received","requestId":"...","headers":[["Path","audio"],["X-RequestId","..."],["X-StreamId","..."]],"binary":"UklGRi..."}
+//! {"time":1700000000456,"direction":"received","requestId":"...","close":{"code":1007,"reason":"..."}}
+//! ```
+//!
+//! `time` is the number of milliseconds since the Unix epoch. `headers` holds all of the message's headers in the
+//! order they were sent or received, and binary bodies are base64-encoded.
+//!
+//! A [`Recording`] reads such a file back. Its [`ReplayTransport`] plays the recorded turns back to a synthesiser in
+//! place of the service, making issues caused by unexpected messages reproducible offline;
+//! [`Recording::replay`] does so for a single turn.
+
+use std::{
+	collections::VecDeque,
+	fmt,
+	fs::File,
+	io::{self, BufRead, BufReader, BufWriter, Write},
+	path::Path,
+	sync::{Arc, Mutex, PoisonError},
+	time::{Duration, SystemTime, UNIX_EPOCH}
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use futures_util::{Stream, TryStreamExt, future::BoxFuture};
+use simd_json::{OwnedValue, prelude::*};
+use tokio::time::Instant;
+
+use crate::{
+	AzureCognitiveSpeechServicesSynthesiser, AzureOutputFormat, Error, SsmlTextMap, SynthesisEvent,
+	message::{
+		AzureCognitiveSpeechServicesMessage, AzureCognitiveSpeechServicesMessageBody, AzureCognitiveSpeechServicesMessageBuilder, MessagePayload,
+		SynthesisContext, SynthesisOptions
+	},
+	transport::{Channel, ConnectRequest, Frame, Transport}
+};
+
+/// The direction a recorded message was sent in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+	/// Sent by the client to the service.
+	Sent,
+	/// Received by the client from the service.
+	Received
+}
+
+impl Direction {
+	fn as_str(&self) -> &'static str {
+		match self {
+			Self::Sent => "sent",
+			Self::Received => "received"
+		}
+	}
+}
+
+/// The contents of a [`RecordedEntry`].
+#[derive(Debug, Clone)]
+pub enum RecordedFrame {
+	/// A protocol message.
+	Message(AzureCognitiveSpeechServicesMessage),
+	/// A WebSocket close frame, received during the turn with the given request ID.
+	Close { request_id: String, code: u16, reason: String }
+}
+
+/// A single message in a [`Recording`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct RecordedEntry {
+	/// When the message was sent or received.
+	pub time: SystemTime,
+	pub direction: Direction,
+	pub frame: RecordedFrame
+}
+
+impl RecordedEntry {
+	/// Returns the request ID of the turn this entry belongs to.
+	pub fn request_id(&self) -> &str {
+		match &self.frame {
+			RecordedFrame::Message(message) => message.request_id(),
+			RecordedFrame::Close { request_id, .. } => request_id
+		}
+	}
+
+	fn to_json(&self) -> String {
+		let mut json = simd_json::owned::Object::new();
+		let time = self.time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+		json.insert("time".into(), time.into());
+		json.insert("direction".into(), self.direction.as_str().into());
+		json.insert("requestId".into(), self.request_id().into());
+		match &self.frame {
+			RecordedFrame::Message(message) => {
+				let headers = message
+					.headers()
+					.map(|(name, value)| OwnedValue::from(vec![OwnedValue::from(name), OwnedValue::from(value)]))
+					.collect::<Vec<_>>();
+				json.insert("headers".into(), headers.into());
+				match message.body() {
+					AzureCognitiveSpeechServicesMessageBody::Text(text) => json.insert("text".into(), text.as_str().into()),
+					AzureCognitiveSpeechServicesMessageBody::Binary(binary) => json.insert("binary".into(), BASE64.encode(binary).into())
+				};
+			}
+			RecordedFrame::Close { code, reason, .. } => {
+				let mut close = simd_json::owned::Object::new();
+				close.insert("code".into(), (*code).into());
+				close.insert("reason".into(), reason.as_str().into());
+				json.insert("close".into(), OwnedValue::from(close));
+			}
+		}
+		OwnedValue::from(json).encode()
+	}
+
+	fn from_json(line: &str) -> Result<Self, String> {
+		let mut line = line.as_bytes().to_vec();
+		let json = simd_json::to_owned_value(&mut line).map_err(|e| e.to_string())?;
+		let field = |name: &'static str| json.get_str(name).ok_or_else(|| format!("missing `{name}`"));
+
+		let time = UNIX_EPOCH + Duration::from_millis(json.get_u64("time").ok_or("missing `time`")?);
+		let direction = match field("direction")? {
+			"sent" => Direction::Sent,
+			"received" => Direction::Received,
+			direction => return Err(format!("unknown direction `{direction}`"))
+		};
+		let request_id = field("requestId")?;
+		let frame = if let Some(close) = json.get("close") {
+			RecordedFrame::Close {
+				request_id: request_id.to_string(),
+				code: close.get_u16("code").ok_or("missing `close.code`")?,
+				reason: close.get_str("reason").unwrap_or_default().to_string()
+			}
+		} else {
+			let headers = json
+				.get_array("headers")
+				.ok_or("missing `headers`")?
+				.iter()
+				.map(|header| match header.as_array().map(Vec::as_slice) {
+					Some([name, value]) => match (name.as_str(), value.as_str()) {
+						(Some(name), Some(value)) => Ok((name.to_string(), value.to_string())),
+						_ => Err("invalid `headers`".to_string())
+					},
+					_ => Err("invalid `headers`".to_string())
+				})
+				.collect::<Result<Vec<_>, _>>()?;
+			let builder = AzureCognitiveSpeechServicesMessageBuilder::from_headers(headers).map_err(|e| e.to_string())?;
+			let builder = match (json.get_str("text"), json.get_str("binary")) {
+				(Some(text), _) => builder.with_body(text),
+				(None, Some(binary)) => builder.with_body(BASE64.decode(binary).map_err(|e| format!("invalid `binary`: {e}"))?),
+				(None, None) => return Err("missing `text` or `binary`".to_string())
+			};
+			RecordedFrame::Message(builder.build().map_err(|e| e.to_string())?)
+		};
+		Ok(Self { time, direction, frame })
+	}
+}
+
+/// Writes the messages exchanged with the speech service to a recording. See the [module docs](self).
+///
+/// Cloning a recorder is cheap, and clones write to the same destination. Failures to write are logged and otherwise
+/// ignored, so recording never interferes with synthesis.
+///
+/// The writer is only flushed at the end of each turn and when the last clone of the recorder is dropped, so writes
+/// made during a turn should be buffered, as [`Recorder::create`] does.
+#[derive(Clone)]
+pub struct Recorder {
+	writer: Arc<Mutex<RecorderWriter>>
+}
+
+struct RecorderWriter(Box<dyn Write + Send>);
+
+impl Drop for RecorderWriter {
+	fn drop(&mut self) {
+		if let Err(e) = self.0.flush() {
+			tracing::warn!("failed to flush recording: {e}");
+		}
+	}
+}
+
+impl Recorder {
+	/// Creates a recorder which writes to `writer`.
+	pub fn new(writer: impl Write + Send + 'static) -> Self {
+		Self {
+			writer: Arc::new(Mutex::new(RecorderWriter(Box::new(writer))))
+		}
+	}
+
+	/// Creates a recorder which writes to the file at `path`, truncating it if it exists.
+	pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+		Ok(Self::new(BufWriter::new(File::create(path)?)))
+	}
+
+	/// Flushes all entries recorded so far to the underlying writer.
+	pub fn flush(&self) -> io::Result<()> {
+		self.writer.lock().unwrap_or_else(PoisonError::into_inner).0.flush()
+	}
+
+	pub(crate) fn record(&self, direction: Direction, message: &AzureCognitiveSpeechServicesMessage) {
+		self.write(RecordedEntry {
+			time: SystemTime::now(),
+			direction,
+			frame: RecordedFrame::Message(message.clone())
+		});
+		if direction == Direction::Received && message.path() == "turn.end" {
+			self.flush_logged();
+		}
+	}
+
+	pub(crate) fn record_close(&self, request_id: &str, code: u16, reason: &str) {
+		self.write(RecordedEntry {
+			time: SystemTime::now(),
+			direction: Direction::Received,
+			frame: RecordedFrame::Close {
+				request_id: request_id.to_string(),
+				code,
+				reason: reason.to_string()
+			}
+		});
+		self.flush_logged();
+	}
+
+	fn write(&self, entry: RecordedEntry) {
+		let mut line = entry.to_json();
+		line.push('\n');
+		if let Err(e) = self.writer.lock().unwrap_or_else(PoisonError::into_inner).0.write_all(line.as_bytes()) {
+			tracing::warn!("failed to write recording: {e}");
+		}
+	}
+
+	fn flush_logged(&self) {
+		if let Err(e) = self.flush() {
+			tracing::warn!("failed to flush recording: {e}");
+		}
+	}
+}
+
+impl fmt::Debug for Recorder {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Recorder").finish_non_exhaustive()
+	}
+}
+
+/// A recording of the messages exchanged with the speech service, as written by a [`Recorder`].
+#[derive(Debug, Clone, Default)]
+pub struct Recording {
+	entries: Vec<RecordedEntry>
+}
+
+impl Recording {
+	/// Reads a recording from JSON lines. Blank lines are skipped.
+	pub fn read(reader: impl BufRead) -> crate::Result<Self> {
+		let mut entries = Vec::new();
+		for (i, line) in reader.lines().enumerate() {
+			let line = line?;
+			if line.trim().is_empty() {
+				continue;
+			}
+			entries.push(RecordedEntry::from_json(&line).map_err(|e| Error::InvalidRecording(format!("line {}: {e}", i + 1)))?);
+		}
+		Ok(Self { entries })
+	}
+
+	/// Reads the recording at `path`.
+	pub fn open(path: impl AsRef<Path>) -> crate::Result<Self> {
+		Self::read(BufReader::new(File::open(path)?))
+	}
+
+	/// Returns all entries of the recording, in the order they were recorded.
+	pub fn entries(&self) -> &[RecordedEntry] {
+		&self.entries
+	}
+
+	/// Returns the request IDs of all turns in the recording, in the order they were started.
+	pub fn request_ids(&self) -> impl Iterator<Item = &str> + '_ {
+		self.entries.as_slice().iter().filter_map(|entry| match &entry.frame {
+			RecordedFrame::Message(message) if entry.direction == Direction::Sent && message.path() == "synthesis.context" => Some(message.request_id()),
+			_ => None
+		})
+	}
+
+	/// Returns a [`ReplayTransport`] which answers each turn started on it with the next turn of this recording.
+	pub fn transport(&self) -> ReplayTransport {
+		ReplayTransport::new(self.request_ids().filter_map(|request_id| self.turn(request_id)))
+	}
+
+	/// Replays the messages received during the turn with the given request ID, producing the same events & errors as
+	/// the original synthesis did.
+	///
+	/// The turn is replayed through a synthesiser using a [`ReplayTransport`], with the recorded SSML, output format &
+	/// metadata options, and the default [`Timeouts`](crate::Timeouts). Text ranges of boundary events always point
+	/// into the recorded SSML, even if the turn originally synthesised raw text. The replayed turn is assigned a new
+	/// request ID.
+	pub fn replay(&self, request_id: &str) -> impl Stream<Item = crate::Result<SynthesisEvent>> + Send + 'static {
+		let turn = self.replay_inner(request_id);
+		futures_util::stream::once(async move {
+			let (synthesiser, ssml, context) = turn?;
+			let output_format = context.audio.output_format.parse::<AzureOutputFormat>()?;
+			synthesiser
+				.speak_inner(SsmlTextMap::new(ssml), false, output_format, context.audio.metadata_options, false)
+				.await
+		})
+		.try_flatten()
+	}
+
+	fn replay_inner(&self, request_id: &str) -> crate::Result<(AzureCognitiveSpeechServicesSynthesiser, String, SynthesisOptions)> {
+		let sent = |path: &'static str| {
+			self.entries.iter().find_map(|entry| match &entry.frame {
+				RecordedFrame::Message(message)
+					if entry.direction == Direction::Sent && message.path() == path && message.request_id().eq_ignore_ascii_case(request_id) =>
+				{
+					Some(message.clone())
+				}
+				_ => None
+			})
+		};
+		let missing = |path: &str| Error::InvalidRecording(format!("no `{path}` recorded for request ID `{request_id}`"));
+		let context = sent(SynthesisContext::PATH).ok_or_else(|| missing(SynthesisContext::PATH))?;
+		let ssml = sent("ssml")
+			.and_then(|message| message.into_body().into_text())
+			.ok_or_else(|| missing("ssml"))?;
+		let turn = self.turn(request_id).ok_or_else(|| missing("ssml"))?;
+		let synthesiser = AzureCognitiveSpeechServicesSynthesiser::builder()
+			.with_endpoint("wss://replay.invalid")
+			.with_subscription_key("replay")
+			.with_transport(ReplayTransport::new([turn]))
+			.build()?;
+		Ok((synthesiser, ssml, context.into_payload::<SynthesisContext>()?.synthesis))
+	}
+
+	/// Collects the frames received during the turn with the given request ID, timed relative to when its `ssml` was
+	/// sent.
+	fn turn(&self, request_id: &str) -> Option<RecordedTurn> {
+		let entries = self
+			.entries
+			.as_slice()
+			.iter()
+			.filter(|entry| entry.request_id().eq_ignore_ascii_case(request_id));
+		let started = entries.clone().find_map(|entry| match &entry.frame {
+			RecordedFrame::Message(message) if entry.direction == Direction::Sent && message.path() == "ssml" => Some(entry.time),
+			_ => None
+		})?;
+		let frames = entries
+			.filter(|entry| entry.direction == Direction::Received)
+			.map(|entry| (entry.time.duration_since(started).unwrap_or_default(), entry.frame.clone()))
+			.collect();
+		Some(RecordedTurn { frames })
+	}
+}
+
+/// The frames received during a recorded turn, with the time each was received at relative to the start of the turn.
+#[derive(Debug, Clone)]
+struct RecordedTurn {
+	frames: Vec<(Duration, RecordedFrame)>
+}
+
+/// A [`Transport`] which replays the turns of a [`Recording`] instead of connecting to the speech service, so
+/// recorded issues can be reproduced through a synthesiser configured with
+/// [`with_transport`](crate::AzureCognitiveSpeechServicesSynthesiserBuilder::with_transport).
+///
+/// Each turn started on the transport, on any of its connections, is answered with the frames received during the
+/// next recorded turn, regardless of the SSML sent. Frames are delivered with the same timing as they were recorded,
+/// and their request IDs are rewritten to match the turn being synthesised. After a recorded close frame, or if the
+/// recording ends before the turn does, the connection is closed. Starting more turns than were recorded fails with
+/// [`Error::InvalidRecording`].
+///
+/// Cloning the transport is cheap, and clones share the remaining turns.
+#[derive(Debug, Clone)]
+pub struct ReplayTransport {
+	turns: Arc<Mutex<VecDeque<RecordedTurn>>>
+}
+
+impl ReplayTransport {
+	fn new(turns: impl IntoIterator<Item = RecordedTurn>) -> Self {
+		Self {
+			turns: Arc::new(Mutex::new(turns.into_iter().collect()))
+		}
+	}
+}
+
+impl Transport for ReplayTransport {
+	fn connect(&self, _request: ConnectRequest) -> BoxFuture<'_, crate::Result<Box<dyn Channel>>> {
+		Box::pin(async move {
+			Ok(Box::new(ReplayChannel {
+				turns: Arc::clone(&self.turns),
+				frames: VecDeque::new(),
+				open: true
+			}) as Box<dyn Channel>)
+		})
+	}
+}
+
+struct ReplayChannel {
+	turns: Arc<Mutex<VecDeque<RecordedTurn>>>,
+	/// The frames of the current turn yet to be received, with the instant each is due at.
+	frames: VecDeque<(Instant, Frame)>,
+	/// Whether the connection stays open once all frames of the current turn have been received.
+	open: bool
+}
+
+impl Channel for ReplayChannel {
+	fn send(&mut self, message: AzureCognitiveSpeechServicesMessage) -> BoxFuture<'_, crate::Result<()>> {
+		Box::pin(async move {
+			if message.path() != "ssml" {
+				return Ok(());
+			}
+			let turn = self
+				.turns
+				.lock()
+				.unwrap_or_else(PoisonError::into_inner)
+				.pop_front()
+				.ok_or_else(|| Error::InvalidRecording("no more recorded turns to replay".to_string()))?;
+			let started = Instant::now();
+			self.open = false;
+			for (at, frame) in turn.frames {
+				let frame = match frame {
+					RecordedFrame::Message(recorded) => {
+						self.open = recorded.path() == "turn.end";
+						let headers = recorded
+							.headers()
+							.map(|(name, value)| {
+								let value = if name.eq_ignore_ascii_case("X-RequestId") { message.request_id() } else { value };
+								(name.to_string(), value.to_string())
+							})
+							.collect();
+						Frame::Message(
+							AzureCognitiveSpeechServicesMessageBuilder::from_headers(headers)?
+								.with_body(recorded.into_body())
+								.build()?
+						)
+					}
+					RecordedFrame::Close { code, reason, .. } => {
+						self.open = false;
+						Frame::Close { code, reason }
+					}
+				};
+				self.frames.push_back((started + at, frame));
+			}
+			Ok(())
+		})
+	}
+
+	fn receive(&mut self) -> BoxFuture<'_, Option<crate::Result<Frame>>> {
+		Box::pin(async move {
+			let Some((at, _)) = self.frames.front() else {
+				return if self.open { std::future::pending().await } else { None };
+			};
+			tokio::time::sleep_until(*at).await;
+			self.frames.pop_front().map(|(_, frame)| Ok(frame))
+		})
+	}
+
+	fn close(&mut self) -> BoxFuture<'_, crate::Result<()>> {
+		Box::pin(async move {
+			self.frames.clear();
+			self.open = false;
+			Ok(())
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	use futures_util::{StreamExt, TryStreamExt};
+	use speech_synthesis::UtteranceConfig;
+
+	use super::*;
+	use crate::testing::{self, MockServer, MockTurn};
+
+	/// Writes to a shared buffer, counting flushes.
+	struct SharedBuffer(Arc<Mutex<Vec<u8>>>, Arc<AtomicUsize>);
+
+	impl Write for SharedBuffer {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			self.0.lock().unwrap().write(buf)
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			self.1.fetch_add(1, Ordering::Relaxed);
+			Ok(())
+		}
+	}
+
+	#[tokio::test]
+	async fn test_round_trip() -> crate::Result<()> {
+		let buffer = Arc::new(Mutex::new(Vec::new()));
+		let flushes = Arc::new(AtomicUsize::new(0));
+		let recorder = Recorder::new(SharedBuffer(Arc::clone(&buffer), Arc::clone(&flushes)));
+		let message = |path: &str, body: AzureCognitiveSpeechServicesMessageBody| {
+			AzureCognitiveSpeechServicesMessage::builder(path, "abc")
+				.with_content_type(AzureCognitiveSpeechServicesMessage::CONTENT_TYPE_JSON)
+				.with_body(body)
+				.build()
+		};
+		let context = r#"{"synthesis":{"audio":{"metadataOptions":{},"outputFormat":"raw-16khz-16bit-mono-pcm"}}}"#;
+		recorder.record(Direction::Sent, &message("synthesis.context", context.into())?);
+		recorder.record(Direction::Sent, &message("ssml", "<speak>Hello</speak>".into())?);
+		recorder.record(Direction::Received, &message("turn.start", "{}".into())?);
+		let audio = AzureCognitiveSpeechServicesMessage::builder("audio", "abc")
+			.with_timestamp("2024-03-01T12:34:56.789Z")
+			.with_stream_id("XYZ")
+			.with_header("X-Extra", "1")
+			.with_body(vec![0; 3200])
+			.build()?;
+		recorder.record(Direction::Received, &audio);
+		assert_eq!(flushes.load(Ordering::Relaxed), 0);
+		recorder.record_close("abc", 1011, "Internal error");
+		assert_eq!(flushes.load(Ordering::Relaxed), 1);
+
+		let recording = Recording::read(&buffer.lock().unwrap()[..])?;
+		assert_eq!(recording.entries().len(), 5);
+		assert_eq!(recording.request_ids().collect::<Vec<_>>(), ["abc"]);
+		match &recording.entries()[3].frame {
+			RecordedFrame::Message(message) => {
+				assert_eq!(
+					message.headers().collect::<Vec<_>>(),
+					[
+						("Path", "audio"),
+						("X-RequestId", "abc"),
+						("X-Timestamp", "2024-03-01T12:34:56.789Z"),
+						("X-StreamId", "XYZ"),
+						("X-Extra", "1")
+					]
+				);
+				assert_eq!(message, &audio);
+			}
+			frame => panic!("expected audio message, got {frame:?}")
+		}
+
+		let events = recording.replay("abc").collect::<Vec<_>>().await;
+		assert!(matches!(&events[0], Ok(SynthesisEvent::AudioChunk(audio)) if audio.len() == 3200));
+		assert!(matches!(&events[1], Err(Error::ServiceUnavailable(e)) if e.reason == "Internal error"));
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_record_replay() -> crate::Result<()> {
+		let server = MockServer::start_with([MockTurn::new()
+			.with_turn_start()
+			.with_response()
+			.with_audio(vec![1; 4800])
+			.with_bookmark(100, "mark")
+			.with_turn_end()])
+		.await?;
+
+		let path = std::env::temp_dir().join(format!("acss-recording-{}.jsonl", std::process::id()));
+		let synthesiser = server.synthesiser_builder().with_recorder(Recorder::create(&path)?).build()?;
+		let live = testing::synthesise(&synthesiser, &UtteranceConfig::default()).await?;
+		let recording = Recording::open(&path)?;
+		std::fs::remove_file(&path)?;
+
+		let request_id = recording.request_ids().next().unwrap().to_string();
+		assert_eq!(request_id, server.requests()[0].request_id);
+		let replayed: Vec<_> = recording.replay(&request_id).try_collect().await?;
+		assert_eq!(format!("{:?}", &live[..live.len() - 1]), format!("{:?}", &replayed[..replayed.len() - 1]));
+		assert!(matches!(replayed.last(), Some(SynthesisEvent::Metrics(metrics)) if metrics.audio_bytes == 4800));
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_replay_transport() -> crate::Result<()> {
+		let server = MockServer::start_with([
+			MockTurn::new()
+				.with_turn_start()
+				.with_response()
+				.with_word_boundary(50, 100, "Hello")
+				.with_audio(vec![1; 3200])
+				.with_turn_end(),
+			MockTurn::new().with_turn_start().with_close(1011, "Internal error")
+		])
+		.await?;
+		let buffer = Arc::new(Mutex::new(Vec::new()));
+		let recorder = Recorder::new(SharedBuffer(Arc::clone(&buffer), Arc::default()));
+		let synthesiser = server.synthesiser_builder().with_recorder(recorder).build()?;
+		let live = testing::synthesise(&synthesiser, &UtteranceConfig::default()).await?;
+		assert!(testing::synthesise(&synthesiser, &UtteranceConfig::default()).await.is_err());
+
+		let recording = Recording::read(&buffer.lock().unwrap()[..])?;
+		let synthesiser = AzureCognitiveSpeechServicesSynthesiser::builder()
+			.with_region("westus2")
+			.with_subscription_key("key")
+			.with_transport(recording.transport())
+			.build()?;
+		let replayed = testing::synthesise(&synthesiser, &UtteranceConfig::default()).await?;
+		assert_eq!(format!("{:?}", &live[..live.len() - 1]), format!("{:?}", &replayed[..replayed.len() - 1]));
+		match testing::synthesise(&synthesiser, &UtteranceConfig::default()).await {
+			Err(Error::ServiceUnavailable(e)) => assert_eq!(e.reason, "Internal error"),
+			r => panic!("expected service unavailable error, got {r:?}")
+		}
+		assert!(matches!(testing::synthesise(&synthesiser, &UtteranceConfig::default()).await, Err(Error::InvalidRecording(_))));
+		Ok(())
+	}
+}
@@ -0,0 +1,408 @@
+//! Subtitles in the SRT & WebVTT formats, built from the word & sentence boundaries of synthesised utterances.
+//!
+//! A [`SubtitleBuilder`] consumes the [`UtteranceEvent`]s of one or more utterances. Utterances are assumed to be
+//! played back to back, so the cues of each utterance are offset by the total audio duration of the ones before it.
+//!
+//! ```no_run
+//! # use azure_cognitive_speech_services::{AzureCognitiveSpeechServicesSynthesiser, AzureOutputFormat, subtitles::SubtitleBuilder};
+//! # use speech_synthesis::{SpeechSynthesiser, UtteranceConfig};
+//! # async fn f(synthesiser: AzureCognitiveSpeechServicesSynthesiser) -> azure_cognitive_speech_services::Result<()> {
+//! let format = AzureOutputFormat::Riff24Khz16BitMonoPcm.to_audio_format().unwrap();
+//! let config = UtteranceConfig::default().with_emit_word_boundary_events(true).with_emit_sentence_boundary_events(true);
+//! let mut subtitles = SubtitleBuilder::default().with_audio_format(&format);
+//! subtitles.push_stream(synthesiser.synthesise_text_stream("Hello, world!", &format, &config).await?).await?;
+//! subtitles.push_stream(synthesiser.synthesise_text_stream("How are you?", &format, &config).await?).await?;
+//! println!("{}", subtitles.to_webvtt());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{fmt::Write, time::Duration};
+
+use futures_util::{Stream, StreamExt};
+use speech_synthesis::{AudioFormat, UtteranceEvent};
+
+use crate::AzureOutputFormat;
+
+/// Options controlling how words are grouped into subtitle cues.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct SubtitleOptions {
+	/// The maximum number of characters per line. Longer words are put on a line of their own.
+	pub max_line_length: usize,
+	/// The maximum number of lines per cue.
+	pub max_lines: usize,
+	/// The maximum duration of a cue.
+	pub max_cue_duration: Duration,
+	/// Whether to start a new cue at the beginning of each sentence.
+	pub split_sentences: bool
+}
+
+impl Default for SubtitleOptions {
+	fn default() -> Self {
+		Self {
+			max_line_length: 42,
+			max_lines: 2,
+			max_cue_duration: Duration::from_secs(7),
+			split_sentences: true
+		}
+	}
+}
+
+impl SubtitleOptions {
+	/// Configures the maximum number of characters per line.
+	pub fn with_max_line_length(mut self, max_line_length: usize) -> Self {
+		self.max_line_length = max_line_length;
+		self
+	}
+
+	/// Configures the maximum number of lines per cue.
+	pub fn with_max_lines(mut self, max_lines: usize) -> Self {
+		self.max_lines = max_lines;
+		self
+	}
+
+	/// Configures the maximum duration of a cue.
+	pub fn with_max_cue_duration(mut self, max_cue_duration: Duration) -> Self {
+		self.max_cue_duration = max_cue_duration;
+		self
+	}
+
+	/// Configures whether to start a new cue at the beginning of each sentence.
+	pub fn with_split_sentences(mut self, split_sentences: bool) -> Self {
+		self.split_sentences = split_sentences;
+		self
+	}
+}
+
+/// A single subtitle cue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cue {
+	/// When the cue is shown, relative to the beginning of the first utterance.
+	pub start: Duration,
+	/// When the cue is hidden, relative to the beginning of the first utterance.
+	pub end: Duration,
+	/// The lines of text shown.
+	pub lines: Vec<String>
+}
+
+#[derive(Debug, Clone)]
+struct Word {
+	from_millis: f64,
+	to_millis: f64,
+	text: String,
+	/// Whether this word begins a sentence reported by a `SentenceBoundary`.
+	starts_sentence: bool,
+	/// Whether this word is the first of its utterance.
+	starts_utterance: bool
+}
+
+/// The sentence currently being spoken, used to restore the punctuation missing from word boundaries.
+#[derive(Debug, Clone)]
+struct Sentence {
+	text: String,
+	/// The end of the last word found in the text.
+	matched: usize,
+	/// The end of the text already displayed.
+	displayed: usize
+}
+
+/// Builds subtitles from the [`UtteranceEvent`]s of one or more consecutive utterances.
+///
+/// Word boundary events must be requested with
+/// [`UtteranceConfig::with_emit_word_boundary_events`](speech_synthesis::UtteranceConfig::with_emit_word_boundary_events).
+/// Requesting sentence boundary events as well allows cues to be split by sentence, and restores the punctuation
+/// which word boundaries lack.
+#[derive(Debug, Clone, Default)]
+pub struct SubtitleBuilder {
+	options: SubtitleOptions,
+	audio_format: Option<AzureOutputFormat>,
+	words: Vec<Word>,
+	/// The start of the current utterance, relative to the beginning of the first utterance.
+	offset_millis: f64,
+	audio_bytes: u64,
+	/// The end of the last boundary in the current utterance, relative to its start.
+	boundary_end_millis: f64,
+	sentence: Option<Sentence>,
+	sentence_started: bool,
+	utterance_ended: bool
+}
+
+impl SubtitleBuilder {
+	pub fn new(options: SubtitleOptions) -> Self {
+		Self { options, ..Self::default() }
+	}
+
+	/// Configures the format of the synthesised audio, so the duration of each utterance can be measured from its
+	/// audio chunks. Without it, or for variable bitrate formats, each utterance is assumed to end with its last
+	/// boundary; use [`SubtitleBuilder::end_utterance_with_duration`] to give the exact duration instead.
+	pub fn with_audio_format(mut self, audio_format: &AudioFormat) -> Self {
+		self.audio_format = AzureOutputFormat::from_audio_format(audio_format);
+		self
+	}
+
+	/// Adds an event of the current utterance. Events other than word & sentence boundaries and audio chunks are
+	/// ignored.
+	pub fn push(&mut self, event: &UtteranceEvent) {
+		match event {
+			UtteranceEvent::WordBoundary { from_millis, to_millis, text } => {
+				self.boundary_end_millis = self.boundary_end_millis.max(f64::from(*to_millis));
+				let (from_millis, to_millis) = (self.offset_millis + f64::from(*from_millis), self.offset_millis + f64::from(*to_millis));
+				match self.display_text(text) {
+					Some(text) => self.words.push(Word {
+						from_millis,
+						to_millis,
+						text,
+						starts_sentence: std::mem::take(&mut self.sentence_started),
+						starts_utterance: std::mem::take(&mut self.utterance_ended) || self.words.is_empty()
+					}),
+					// The word is part of the text already displayed by the previous word, e.g. the second half of a
+					// hyphenated word.
+					None => {
+						if let Some(word) = self.words.last_mut() {
+							word.to_millis = word.to_millis.max(to_millis);
+						}
+					}
+				}
+			}
+			UtteranceEvent::SentenceBoundary { to_millis, text, .. } => {
+				self.boundary_end_millis = self.boundary_end_millis.max(f64::from(*to_millis));
+				self.sentence = Some(Sentence {
+					text: text.to_string(),
+					matched: 0,
+					displayed: 0
+				});
+				self.sentence_started = true;
+			}
+			UtteranceEvent::AudioChunk(audio) => {
+				self.audio_bytes += audio.len() as u64;
+			}
+			_ => {}
+		}
+	}
+
+	/// Adds all events of an utterance's stream, then [ends the utterance](SubtitleBuilder::end_utterance).
+	pub async fn push_stream<E>(&mut self, events: impl Stream<Item = Result<UtteranceEvent, E>>) -> Result<(), E> {
+		let mut events = std::pin::pin!(events);
+		while let Some(event) = events.next().await {
+			self.push(&event?);
+		}
+		self.end_utterance();
+		Ok(())
+	}
+
+	/// Ends the current utterance, so the events of the next one are offset by its duration.
+	pub fn end_utterance(&mut self) {
+		let duration = self
+			.audio_format
+			.and_then(|format| format.audio_duration(self.audio_bytes))
+			.filter(|_| self.audio_bytes > 0)
+			.unwrap_or_else(|| Duration::from_secs_f64(self.boundary_end_millis / 1000.));
+		self.end_utterance_with_duration(duration);
+	}
+
+	/// Ends the current utterance, so the events of the next one are offset by the given duration.
+	pub fn end_utterance_with_duration(&mut self, duration: Duration) {
+		self.offset_millis += duration.as_secs_f64() * 1000.;
+		self.audio_bytes = 0;
+		self.boundary_end_millis = 0.;
+		self.sentence = None;
+		self.sentence_started = false;
+		self.utterance_ended = true;
+	}
+
+	/// Returns the text to display for a spoken word, including any punctuation surrounding it in the current
+	/// sentence, or `None` if the word has already been displayed as part of a previous word.
+	fn display_text(&mut self, word: &str) -> Option<String> {
+		let Some(sentence) = self.sentence.as_mut() else {
+			return Some(word.to_string());
+		};
+		let text = sentence.text.as_str();
+		let Some(pos) = text
+			.get(sentence.matched..)
+			.and_then(|rest| rest.find(word))
+			.map(|pos| sentence.matched + pos)
+		else {
+			return Some(word.to_string());
+		};
+		sentence.matched = pos + word.len();
+		if sentence.matched <= sentence.displayed {
+			return None;
+		}
+
+		// Display the whole whitespace-delimited token containing the word.
+		let start = text[..pos]
+			.char_indices()
+			.rfind(|(_, c)| c.is_whitespace())
+			.map_or(0, |(i, c)| i + c.len_utf8())
+			.max(sentence.displayed);
+		let end = text[sentence.matched..]
+			.find(char::is_whitespace)
+			.map_or(text.len(), |i| sentence.matched + i);
+		sentence.displayed = end;
+		Some(text[start..end].trim().to_string())
+	}
+
+	/// Groups the words of all utterances so far into cues.
+	pub fn cues(&self) -> Vec<Cue> {
+		let max_cue_millis = self.options.max_cue_duration.as_secs_f64() * 1000.;
+		let mut cues = Vec::new();
+		let mut current: Vec<&Word> = Vec::new();
+		for word in &self.words {
+			if let (Some(first), Some(last)) = (current.first(), current.last()) {
+				let new_sentence = self.options.split_sentences && (word.starts_sentence || ends_sentence(&last.text));
+				let too_long = word.to_millis - first.from_millis > max_cue_millis
+					|| wrap(current.iter().chain([&word]).map(|word| word.text.as_str()), self.options.max_line_length).len() > self.options.max_lines;
+				if word.starts_utterance || new_sentence || too_long {
+					cues.push(self.cue(&current));
+					current.clear();
+				}
+			}
+			current.push(word);
+		}
+		if !current.is_empty() {
+			cues.push(self.cue(&current));
+		}
+		cues
+	}
+
+	fn cue(&self, words: &[&Word]) -> Cue {
+		let millis = |millis: f64| Duration::from_secs_f64(millis.max(0.) / 1000.);
+		let start = millis(words[0].from_millis);
+		Cue {
+			start,
+			end: millis(words[words.len() - 1].to_millis).max(start),
+			lines: wrap(words.iter().map(|word| word.text.as_str()), self.options.max_line_length)
+		}
+	}
+
+	/// Renders the subtitles as an SRT document.
+	pub fn to_srt(&self) -> String {
+		let mut srt = String::new();
+		for (i, cue) in self.cues().into_iter().enumerate() {
+			let _ = write!(srt, "{}\n{} --> {}\n{}\n\n", i + 1, timestamp(cue.start, ','), timestamp(cue.end, ','), cue.lines.join("\n"));
+		}
+		srt
+	}
+
+	/// Renders the subtitles as a WebVTT document.
+	pub fn to_webvtt(&self) -> String {
+		let mut vtt = String::from("WEBVTT\n\n");
+		for cue in self.cues() {
+			let text = cue.lines.join("\n").replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+			let _ = write!(vtt, "{} --> {}\n{text}\n\n", timestamp(cue.start, '.'), timestamp(cue.end, '.'));
+		}
+		vtt
+	}
+}
+
+fn ends_sentence(text: &str) -> bool {
+	text.trim_end_matches(['"', '\'', ')', '”', '’']).ends_with(['.', '!', '?', '…'])
+}
+
+/// Greedily wraps words into lines of at most `max_length` characters.
+fn wrap<'w>(words: impl Iterator<Item = &'w str>, max_length: usize) -> Vec<String> {
+	let mut lines: Vec<String> = Vec::new();
+	for word in words {
+		match lines.last_mut() {
+			Some(line) if line.chars().count() + 1 + word.chars().count() <= max_length => {
+				line.push(' ');
+				line.push_str(word);
+			}
+			_ => lines.push(word.to_string())
+		}
+	}
+	lines
+}
+
+fn timestamp(duration: Duration, separator: char) -> String {
+	let millis = duration.as_millis();
+	format!("{:02}:{:02}:{:02}{separator}{:03}", millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60, millis % 1000)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn word(from_millis: f32, to_millis: f32, text: &str) -> UtteranceEvent {
+		UtteranceEvent::WordBoundary {
+			from_millis,
+			to_millis,
+			text: text.into()
+		}
+	}
+
+	fn sentence(from_millis: f32, to_millis: f32, text: &str) -> UtteranceEvent {
+		UtteranceEvent::SentenceBoundary {
+			from_millis,
+			to_millis,
+			text: text.into()
+		}
+	}
+
+	#[test]
+	fn test_concatenated_utterances() {
+		let format = AzureOutputFormat::Raw16Khz16BitMonoPcm.to_audio_format().unwrap();
+		let mut subtitles = SubtitleBuilder::default().with_audio_format(&format);
+		for event in [
+			sentence(100., 900., "\"Hello, world.\""),
+			word(100., 400., "Hello"),
+			word(450., 900., "world"),
+			sentence(1000., 1500., "Well-known words!"),
+			word(1000., 1200., "Well"),
+			word(1200., 1300., "known"),
+			word(1300., 1500., "words"),
+			UtteranceEvent::AudioChunk(vec![0; 64_000].into_boxed_slice())
+		] {
+			subtitles.push(&event);
+		}
+		subtitles.end_utterance();
+		subtitles.push(&word(100., 500., "Again"));
+		subtitles.end_utterance();
+
+		assert_eq!(
+			subtitles.to_srt(),
+			concat!(
+				"1\n00:00:00,100 --> 00:00:00,900\n\"Hello, world.\"\n\n",
+				"2\n00:00:01,000 --> 00:00:01,500\nWell-known words!\n\n",
+				"3\n00:00:02,100 --> 00:00:02,500\nAgain\n\n"
+			)
+		);
+	}
+
+	#[test]
+	fn test_multi_word_boundaries() {
+		let mut subtitles = SubtitleBuilder::default();
+		for event in [
+			sentence(0., 1500., "It opens May 5, 2024 at noon."),
+			word(0., 200., "It"),
+			word(200., 500., "opens"),
+			word(500., 1100., "May 5, 2024"),
+			word(1100., 1200., "at"),
+			word(1200., 1500., "noon")
+		] {
+			subtitles.push(&event);
+		}
+		assert_eq!(subtitles.cues()[0].lines, ["It opens May 5, 2024 at noon."]);
+	}
+
+	#[test]
+	fn test_cue_limits() {
+		let mut subtitles = SubtitleBuilder::new(
+			SubtitleOptions::default()
+				.with_max_line_length(11)
+				.with_max_lines(1)
+				.with_max_cue_duration(Duration::from_millis(250))
+		);
+		for (i, text) in ["AT&T", "one", "two", "<three>"].into_iter().enumerate() {
+			subtitles.push(&word(i as f32 * 100., i as f32 * 100. + 100., text));
+		}
+		assert_eq!(
+			subtitles.to_webvtt(),
+			concat!("WEBVTT\n\n", "00:00:00.000 --> 00:00:00.200\nAT&amp;T one\n\n", "00:00:00.200 --> 00:00:00.400\ntwo &lt;three&gt;\n\n")
+		);
+
+		subtitles.options = subtitles.options.clone().with_max_lines(2).with_max_cue_duration(Duration::from_secs(1));
+		assert_eq!(subtitles.cues()[0].lines, ["AT&T one", "two <three>"]);
+	}
+}
@@ -0,0 +1,303 @@
+use std::sync::Arc;
+
+use http::{HeaderName, HeaderValue, Uri};
+
+use super::{AzureCognitiveSpeechServicesSynthesiser, PoolConfig, RetryPolicy, Timeouts, pool::ConnectionPool};
+use crate::{
+	Error,
+	auth::{Credential, SubscriptionKey},
+	message::SpeechConfigContext,
+	recording::Recorder,
+	transport::{Proxy, Transport, WebSocketTransport}
+};
+
+const WEBSOCKET_PATH: &str = "/cognitiveservices/websocket/v1";
+
+/// The Azure cloud a speech resource is deployed in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AzureCloud {
+	/// The global Azure cloud.
+	#[default]
+	Global,
+	/// Azure China, operated by 21Vianet.
+	China,
+	/// Azure US Government.
+	UsGovernment
+}
+
+impl AzureCloud {
+	fn domain_suffix(&self) -> &'static str {
+		match self {
+			Self::Global => "microsoft.com",
+			Self::China => "azure.cn",
+			Self::UsGovernment => "azure.us"
+		}
+	}
+
+	/// Returns the host of the text-to-speech service for the given region in this cloud.
+	pub fn tts_host(&self, region: &str) -> String {
+		format!("{region}.tts.speech.{}", self.domain_suffix())
+	}
+
+	/// Returns the URL of the `issueToken` endpoint for the given region in this cloud, as used by
+	/// [`IssuedToken::for_cloud`](crate::auth::IssuedToken::for_cloud).
+	pub fn token_endpoint(&self, region: &str) -> String {
+		// The token service doesn't share the TTS host's domain in every cloud.
+		let domain_suffix = match self {
+			Self::Global => "microsoft.com",
+			Self::China => "azure.cn",
+			Self::UsGovernment => "microsoft.us"
+		};
+		format!("https://{region}.api.cognitive.{domain_suffix}/sts/v1.0/issueToken")
+	}
+}
+
+/// Builder for an [`AzureCognitiveSpeechServicesSynthesiser`].
+///
+/// The service endpoint is determined by the first of these that is configured:
+/// - a full endpoint URL via [`with_endpoint`](Self::with_endpoint),
+/// - a host via [`with_host`](Self::with_host),
+/// - a region via [`with_region`](Self::with_region), in the cloud configured with [`with_cloud`](Self::with_cloud).
+#[derive(Default)]
+pub struct AzureCognitiveSpeechServicesSynthesiserBuilder {
+	region: Option<String>,
+	cloud: AzureCloud,
+	host: Option<String>,
+	endpoint: Option<String>,
+	deployment_id: Option<String>,
+	headers: Vec<(String, String)>,
+	subscription_key: Option<String>,
+	credential: Option<Arc<dyn Credential>>,
+	pool: Option<PoolConfig>,
+	retry_policy: Option<RetryPolicy>,
+	timeouts: Timeouts,
+	recorder: Option<Recorder>,
+	transport: Option<Arc<dyn Transport>>,
+	proxy: Option<Proxy>,
+	proxy_from_env: bool,
+	speech_config_context: Option<SpeechConfigContext>
+}
+
+impl AzureCognitiveSpeechServicesSynthesiserBuilder {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Configures the region of the speech resource, e.g. `westus2`.
+	pub fn with_region(mut self, region: impl Into<String>) -> Self {
+		self.region = Some(region.into());
+		self
+	}
+
+	/// Configures the Azure cloud the speech resource is deployed in. Only used in combination with
+	/// [`with_region`](Self::with_region).
+	pub fn with_cloud(mut self, cloud: AzureCloud) -> Self {
+		self.cloud = cloud;
+		self
+	}
+
+	/// Configures the host to connect to, e.g. the custom domain of a speech resource. The connection is made over
+	/// `wss://` to the standard WebSocket path on this host.
+	pub fn with_host(mut self, host: impl Into<String>) -> Self {
+		self.host = Some(host.into());
+		self
+	}
+
+	/// Configures the full WebSocket endpoint URL to connect to. Both `wss://` and `ws://` URLs are accepted.
+	pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+		self.endpoint = Some(endpoint.into());
+		self
+	}
+
+	/// Configures the deployment ID of a custom neural voice endpoint to synthesise with.
+	pub fn with_deployment_id(mut self, deployment_id: impl Into<String>) -> Self {
+		self.deployment_id = Some(deployment_id.into());
+		self
+	}
+
+	/// Adds an extra header to send with every connection request.
+	pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+		self.headers.push((name.into(), value.into()));
+		self
+	}
+
+	/// Configures the synthesiser to authenticate with the resource's subscription key.
+	pub fn with_subscription_key(mut self, key: impl Into<String>) -> Self {
+		self.subscription_key = Some(key.into());
+		self
+	}
+
+	/// Configures the synthesiser to authenticate with the given [`Credential`]. Takes precedence over
+	/// [`with_subscription_key`](Self::with_subscription_key).
+	pub fn with_credential(mut self, credential: impl Credential) -> Self {
+		self.credential = Some(Arc::new(credential));
+		self
+	}
+
+	/// Configures the synthesiser to hand out connections from a pool of warm connections, rather than opening a new
+	/// connection for every utterance.
+	///
+	/// The pool is shared between all clones of the synthesiser, and also limits the number of utterances that can be
+	/// synthesised concurrently to [`PoolConfig::max_connections`].
+	pub fn with_connection_pool(mut self, config: PoolConfig) -> Self {
+		self.pool = Some(config);
+		self
+	}
+
+	/// Configures the synthesiser to retry utterances which fail with a [retryable](Error::is_retryable) error. By
+	/// default, utterances are never retried.
+	pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+		self.retry_policy = Some(policy);
+		self
+	}
+
+	/// Configures timeouts for each stage of a request. Defaults to [`Timeouts::default`].
+	pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+		self.timeouts = timeouts;
+		self
+	}
+
+	/// Records all messages exchanged with the service to `recorder`, for debugging. See [`crate::recording`].
+	pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+		self.recorder = Some(recorder);
+		self
+	}
+
+	/// Configures the [`Transport`] used to connect to the service. Defaults to [`WebSocketTransport`].
+	pub fn with_transport(mut self, transport: impl Transport) -> Self {
+		self.transport = Some(Arc::new(transport));
+		self
+	}
+
+	/// Configures how the client describes itself to the service in each connection's `speech.config` message.
+	/// Defaults to [`SpeechConfigContext::default`], which reports this crate and the OS it's running on.
+	pub fn with_speech_config_context(mut self, context: SpeechConfigContext) -> Self {
+		self.speech_config_context = Some(context);
+		self
+	}
+
+	/// Tunnels connections to the service through `proxy`.
+	///
+	/// This only applies to the default [`WebSocketTransport`]; it is ignored if a custom transport is configured with
+	/// [`with_transport`](Self::with_transport).
+	pub fn with_proxy(mut self, proxy: Proxy) -> Self {
+		self.proxy = Some(proxy);
+		self
+	}
+
+	/// Tunnels connections to the service through the proxy configured by the `HTTPS_PROXY` & `NO_PROXY` environment
+	/// variables, if any; see [`Proxy::from_env`]. A proxy configured with [`with_proxy`](Self::with_proxy) takes
+	/// precedence.
+	pub fn with_proxy_from_env(mut self) -> Self {
+		self.proxy_from_env = true;
+		self
+	}
+
+	fn endpoint(&self) -> crate::Result<Uri> {
+		let mut endpoint = match (&self.endpoint, &self.host, &self.region) {
+			(Some(endpoint), ..) => endpoint.clone(),
+			(None, Some(host), _) => format!("wss://{host}{WEBSOCKET_PATH}"),
+			(None, None, Some(region)) => format!("wss://{}{WEBSOCKET_PATH}", self.cloud.tts_host(region)),
+			(None, None, None) => return Err(Error::InvalidEndpoint("one of region, host, or endpoint must be configured".to_string()))
+		};
+		if let Some(deployment_id) = &self.deployment_id {
+			endpoint.push(if endpoint.contains('?') { '&' } else { '?' });
+			endpoint.push_str("deploymentId=");
+			percent_encode_into(deployment_id, &mut endpoint);
+		}
+
+		let endpoint: Uri = endpoint.parse().map_err(|e| Error::InvalidEndpoint(format!("`{endpoint}`: {e}")))?;
+		match endpoint.scheme_str() {
+			Some("wss" | "ws") => {}
+			_ => return Err(Error::InvalidEndpoint(format!("`{endpoint}`: scheme must be `wss` or `ws`")))
+		}
+		if endpoint.host().is_none() {
+			return Err(Error::InvalidEndpoint(format!("`{endpoint}`: missing host")));
+		}
+		Ok(endpoint)
+	}
+
+	pub fn build(self) -> crate::Result<AzureCognitiveSpeechServicesSynthesiser> {
+		let endpoint = self.endpoint()?;
+		let credential = match (self.credential, self.subscription_key) {
+			(Some(credential), _) => credential,
+			(None, Some(key)) => Arc::new(SubscriptionKey::new(key)?),
+			(None, None) => return Err(Error::MissingCredential)
+		};
+		let headers = self
+			.headers
+			.into_iter()
+			.map(|(name, value)| {
+				let name = HeaderName::try_from(name).map_err(|e| Error::InvalidHeader(e.to_string()))?;
+				let value = HeaderValue::try_from(value).map_err(|e| Error::InvalidHeader(format!("`{name}`: {e}")))?;
+				Ok((name, value))
+			})
+			.collect::<crate::Result<Vec<_>>>()?;
+		let transport = match (self.transport, self.proxy) {
+			(Some(transport), _) => transport,
+			(None, Some(proxy)) => Arc::new(WebSocketTransport::new().with_proxy(proxy)),
+			(None, None) => match self.proxy_from_env.then(Proxy::from_env).transpose()?.flatten() {
+				Some(proxy) => Arc::new(WebSocketTransport::new().with_proxy(proxy)),
+				None => Arc::new(WebSocketTransport::new())
+			}
+		};
+
+		Ok(AzureCognitiveSpeechServicesSynthesiser {
+			endpoint,
+			credential,
+			headers: headers.into(),
+			pool: self.pool.map(|config| Arc::new(ConnectionPool::new(config))),
+			retry_policy: self.retry_policy.unwrap_or_else(RetryPolicy::none),
+			timeouts: self.timeouts,
+			recorder: self.recorder,
+			speech_config_context: Arc::new(self.speech_config_context.unwrap_or_default()),
+			transport
+		})
+	}
+}
+
+/// Percent-encodes `s` for use in a query string, leaving only unreserved characters as-is.
+fn percent_encode_into(s: &str, out: &mut String) {
+	for b in s.bytes() {
+		if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+			out.push(b as char);
+		} else {
+			out.push_str(&format!("%{b:02X}"));
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_endpoint() -> crate::Result<()> {
+		let builder = AzureCognitiveSpeechServicesSynthesiserBuilder::new()
+			.with_region("chinaeast2")
+			.with_cloud(AzureCloud::China);
+		assert_eq!(builder.endpoint()?, "wss://chinaeast2.tts.speech.azure.cn/cognitiveservices/websocket/v1");
+
+		let builder = builder.with_host("my-resource.cognitiveservices.azure.com").with_deployment_id("abc");
+		assert_eq!(builder.endpoint()?, "wss://my-resource.cognitiveservices.azure.com/cognitiveservices/websocket/v1?deploymentId=abc");
+
+		let builder = builder.with_deployment_id("a b&c=d");
+		assert_eq!(builder.endpoint()?, "wss://my-resource.cognitiveservices.azure.com/cognitiveservices/websocket/v1?deploymentId=a%20b%26c%3Dd");
+
+		let builder = builder.with_endpoint("https://example.com/");
+		assert!(matches!(builder.endpoint(), Err(Error::InvalidEndpoint(_))));
+
+		let builder = AzureCognitiveSpeechServicesSynthesiserBuilder::new().with_region("westus2");
+		assert!(matches!(builder.build(), Err(Error::MissingCredential)));
+		Ok(())
+	}
+
+	#[test]
+	fn test_cloud_hosts() {
+		assert_eq!(AzureCloud::Global.token_endpoint("westus2"), "https://westus2.api.cognitive.microsoft.com/sts/v1.0/issueToken");
+		assert_eq!(AzureCloud::China.token_endpoint("chinaeast2"), "https://chinaeast2.api.cognitive.azure.cn/sts/v1.0/issueToken");
+		assert_eq!(AzureCloud::UsGovernment.tts_host("usgovvirginia"), "usgovvirginia.tts.speech.azure.us");
+		assert_eq!(AzureCloud::UsGovernment.token_endpoint("usgovvirginia"), "https://usgovvirginia.api.cognitive.microsoft.us/sts/v1.0/issueToken");
+	}
+}
@@ -0,0 +1,189 @@
+use std::time::{Duration, Instant};
+
+use futures_util::FutureExt;
+use tokio::sync::{OwnedMappedMutexGuard, OwnedMutexGuard};
+
+use super::{AzureCognitiveSpeechServicesSynthesiser, AzureOutputFormat};
+use crate::{
+	Error,
+	message::{AzureCognitiveSpeechServicesMessage, MetadataOptions, SpeechConfig, SynthesisAudioOptions, SynthesisContext, SynthesisOptions},
+	recording::{Direction, Recorder},
+	transport::Channel
+};
+
+/// A connection to the speech service which has already been sent its `speech.config`, and can carry any
+/// number of sequential synthesis turns.
+pub(crate) struct Connection {
+	pub(crate) channel: Box<dyn Channel>,
+	idle: bool,
+	idle_since: Instant,
+	connect_duration: Option<Duration>,
+	recorder: Option<Recorder>
+}
+
+/// Details of a turn started on a [`Connection`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Turn {
+	/// When the turn's request was sent.
+	pub started: Instant,
+	pub output_format: AzureOutputFormat,
+	/// How long it took to open the connection, if this is the first turn on it.
+	pub connect_duration: Option<Duration>
+}
+
+impl Connection {
+	pub async fn open(synthesiser: &AzureCognitiveSpeechServicesSynthesiser) -> crate::Result<Self> {
+		let started = Instant::now();
+		let mut connection = match synthesiser.timeouts.connect {
+			Some(timeout) => tokio::time::timeout(timeout, Self::open_inner(synthesiser))
+				.await
+				.map_err(|_| Error::ConnectTimeout(timeout))??,
+			None => Self::open_inner(synthesiser).await?
+		};
+		connection.connect_duration = Some(started.elapsed());
+		Ok(connection)
+	}
+
+	async fn open_inner(synthesiser: &AzureCognitiveSpeechServicesSynthesiser) -> crate::Result<Self> {
+		let channel = synthesiser.transport.connect(synthesiser.connect_request().await?).await?;
+		let mut connection = Self {
+			channel,
+			idle: true,
+			idle_since: Instant::now(),
+			connect_duration: None,
+			recorder: synthesiser.recorder.clone()
+		};
+		let speech_config = SpeechConfig {
+			context: (*synthesiser.speech_config_context).clone()
+		};
+		connection
+			.send(AzureCognitiveSpeechServicesMessage::from_payload(&speech_config, AzureCognitiveSpeechServicesMessage::gen_request_id())?.build()?)
+			.await?;
+		Ok(connection)
+	}
+
+	async fn send(&mut self, mut message: AzureCognitiveSpeechServicesMessage) -> crate::Result<()> {
+		if let Some(recorder) = &self.recorder {
+			// Timestamp the message now, so the recording has the same `X-Timestamp` as is sent.
+			message.stamp();
+			recorder.record(Direction::Sent, &message);
+		}
+		self.channel.send(message).await
+	}
+
+	/// Returns the recorder messages on this connection should be recorded to, if any.
+	pub fn recorder(&self) -> Option<&Recorder> {
+		self.recorder.as_ref()
+	}
+
+	/// Returns whether another turn can be started on this connection.
+	///
+	/// A connection is only reusable if its last turn ran to `turn.end` and nothing has been received since; the
+	/// service closes sockets which sit idle for too long, and we'd otherwise only notice once the next turn is
+	/// already underway.
+	pub fn is_reusable(&mut self) -> bool {
+		// Any frame (or the connection closing) while idle means the service has given up on this connection.
+		if self.idle && self.channel.receive().now_or_never().is_some() {
+			self.idle = false;
+		}
+		self.idle
+	}
+
+	pub async fn start_turn(
+		&mut self,
+		request_id: &str,
+		ssml_string: &str,
+		output_format: AzureOutputFormat,
+		metadata_options: &MetadataOptions
+	) -> crate::Result<Turn> {
+		self.idle = false;
+		let turn = Turn {
+			started: Instant::now(),
+			output_format,
+			connect_duration: self.connect_duration.take()
+		};
+		let context = SynthesisContext {
+			synthesis: SynthesisOptions {
+				audio: SynthesisAudioOptions {
+					metadata_options: metadata_options.clone(),
+					output_format: output_format.to_string()
+				}
+			}
+		};
+		self.send(AzureCognitiveSpeechServicesMessage::from_payload(&context, request_id)?.build()?)
+			.await?;
+		self.send(
+			AzureCognitiveSpeechServicesMessage::builder("ssml", request_id)
+				.with_content_type(AzureCognitiveSpeechServicesMessage::CONTENT_TYPE_SSML)
+				.with_body(ssml_string)
+				.build()?
+		)
+		.await?;
+		Ok(turn)
+	}
+
+	/// Marks the current turn as complete, allowing the connection to be reused.
+	pub fn end_turn(&mut self) {
+		self.idle = true;
+		self.idle_since = Instant::now();
+	}
+
+	/// Returns how long this connection has been sitting idle, or `None` if a turn is in progress.
+	pub fn idle_duration(&self) -> Option<Duration> {
+		self.idle.then(|| self.idle_since.elapsed())
+	}
+}
+
+/// Starts a turn on the connection held in `slot`, reusing it if possible or transparently replacing it with a fresh
+/// connection if the service has since closed it.
+///
+/// Returns the started turn along with a guard which keeps the slot locked until the turn's stream is dropped, so turns
+/// on the same slot always run sequentially.
+pub(crate) async fn start_turn_in_slot(
+	synthesiser: &AzureCognitiveSpeechServicesSynthesiser,
+	mut slot: OwnedMutexGuard<Option<Connection>>,
+	request_id: &str,
+	ssml_string: &str,
+	output_format: AzureOutputFormat,
+	metadata_options: &MetadataOptions
+) -> crate::Result<(OwnedMappedMutexGuard<Option<Connection>, Connection>, Turn)> {
+	if let Some(mut connection) = slot.take() {
+		if connection.is_reusable() {
+			match connection.start_turn(request_id, ssml_string, output_format, metadata_options).await {
+				Ok(turn) => return Ok((OwnedMutexGuard::map(slot, move |slot| slot.insert(connection)), turn)),
+				Err(e) => tracing::debug!("failed to reuse connection, reconnecting: {e}")
+			}
+		}
+	}
+
+	let mut connection = Connection::open(synthesiser).await.map_err(|e| e.with_request_id(request_id))?;
+	let turn = connection.start_turn(request_id, ssml_string, output_format, metadata_options).await?;
+	Ok((OwnedMutexGuard::map(slot, move |slot| slot.insert(connection)), turn))
+}
+
+#[cfg(test)]
+mod tests {
+	use speech_synthesis::UtteranceConfig;
+
+	use crate::{
+		message::SpeechConfigContext,
+		testing::{self, MockServer}
+	};
+
+	#[tokio::test]
+	async fn test_speech_config_context() -> crate::Result<()> {
+		let server = MockServer::start().await?;
+		testing::synthesise(&server.synthesiser_builder().build()?, &UtteranceConfig::default()).await?;
+		let context = server.speech_configs().remove(0).context;
+		assert_eq!(context.system.name, env!("CARGO_PKG_NAME"));
+		assert_eq!(context.system.version, env!("CARGO_PKG_VERSION"));
+		assert!(context.system.build.starts_with(std::env::consts::OS));
+		assert!(context.application.is_none());
+
+		let context = SpeechConfigContext::default().with_application("narrator", "2.1.0");
+		let synthesiser = server.synthesiser_builder().with_speech_config_context(context.clone()).build()?;
+		testing::synthesise(&synthesiser, &UtteranceConfig::default()).await?;
+		assert_eq!(server.speech_configs()[1].context, context);
+		Ok(())
+	}
+}
@@ -0,0 +1,164 @@
+use std::{ops::Range, time::Duration};
+
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use speech_synthesis::{BlendShapeVisemeFrame, UtteranceEvent};
+
+/// An event emitted by the speech service during synthesis.
+///
+/// This is a superset of [`UtteranceEvent`], covering everything the service can send. Events without an
+/// [`UtteranceEvent`] equivalent are dropped when synthesising through the
+/// [`SpeechSynthesiser`](speech_synthesis::SpeechSynthesiser) trait; see [`SynthesisEvent::into_utterance_event`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SynthesisEvent {
+	/// A chunk of synthesised speech audio in the requested format. The audio shares the buffer of the message it was
+	/// received in.
+	AudioChunk(Bytes),
+	/// Marks the audio offset of an SSML `<bookmark>` (or [`ssml::Mark`]).
+	Bookmark {
+		/// The position in milliseconds the bookmark occurred, relative to the beginning of the audio stream.
+		at_millis: f32,
+		/// The name of the bookmark.
+		mark: Box<str>
+	},
+	/// Marks the time boundary of a spoken word in the audio.
+	WordBoundary {
+		/// The position in milliseconds the spoken word begun, relative to the beginning of the audio stream.
+		from_millis: f32,
+		/// The position in milliseconds the spoken word ended, relative to the beginning of the audio stream.
+		to_millis: f32,
+		/// The text of the word.
+		text: Box<str>,
+		/// The byte range of the text in the submitted input: the input text when synthesising raw text, or the
+		/// serialized SSML otherwise. `None` if the text could not be located in the input.
+		text_range: Option<Range<usize>>
+	},
+	/// Marks the time boundary of a punctuation mark in the audio.
+	PunctuationBoundary {
+		/// The position in milliseconds the punctuation begun, relative to the beginning of the audio stream.
+		from_millis: f32,
+		/// The position in milliseconds the punctuation ended, relative to the beginning of the audio stream.
+		to_millis: f32,
+		/// The punctuation text.
+		text: Box<str>,
+		/// The byte range of the text in the submitted input: the input text when synthesising raw text, or the
+		/// serialized SSML otherwise. `None` if the text could not be located in the input.
+		text_range: Option<Range<usize>>
+	},
+	/// Marks the time boundary of a sentence in the audio.
+	SentenceBoundary {
+		/// The position in milliseconds the sentence begun, relative to the beginning of the audio stream.
+		from_millis: f32,
+		/// The position in milliseconds the sentence ended, relative to the beginning of the audio stream.
+		to_millis: f32,
+		/// The text of the sentence.
+		text: Box<str>,
+		/// The byte range of the text in the submitted input: the input text when synthesising raw text, or the
+		/// serialized SSML otherwise. `None` if the text could not be located in the input.
+		text_range: Option<Range<usize>>
+	},
+	/// A single viseme, identified by Azure's viseme ID.
+	Viseme {
+		/// The position in milliseconds the viseme begins, relative to the beginning of the audio stream.
+		at_millis: f32,
+		/// The Azure viseme ID, from `0` to `21`.
+		viseme_id: u8
+	},
+	/// A chunk of viseme frames in blend shape format.
+	BlendShapeVisemesChunk(Box<[BlendShapeVisemeFrame]>),
+	/// A single viseme with its SVG animation, as requested with [`VisemeType::RedlipsFront`](super::VisemeType).
+	///
+	/// Fragments can be assembled into a single animated SVG document with
+	/// [`SvgVisemeAnimation`](super::SvgVisemeAnimation).
+	SvgViseme {
+		/// The position in milliseconds the viseme begins, relative to the beginning of the audio stream.
+		at_millis: f32,
+		/// The Azure viseme ID, from `0` to `21`.
+		viseme_id: u8,
+		/// The SVG fragment drawing the viseme.
+		svg: Box<str>
+	},
+	/// Marks the end of the synthesis session.
+	SessionEnd {
+		/// The total duration of the synthesised audio in milliseconds.
+		audio_duration_millis: f32
+	},
+	/// Timing measurements of the turn, emitted once as the last event of a successful turn.
+	Metrics(SynthesisMetrics),
+	/// The synthesis failed after events had already been received, and was restarted from the beginning as configured
+	/// by the [`RetryPolicy`](super::RetryPolicy). All events received before this one should be discarded.
+	Restarted {
+		/// The number of the retry, starting from `1`.
+		attempt: u32
+	},
+	/// A metadata event of a type this crate doesn't know about yet.
+	Unknown {
+		/// The type of the metadata, as sent by the service.
+		kind: Box<str>,
+		/// The raw JSON of the metadata entry.
+		raw_json: Box<str>
+	}
+}
+
+impl SynthesisEvent {
+	/// Converts this event into its [`UtteranceEvent`] equivalent, or `None` if it has none.
+	///
+	/// [`UtteranceEvent::AudioChunk`] owns its audio, so the audio of each chunk is copied out of the message it was
+	/// received in. Use the [`Bytes`] of [`SynthesisEvent::AudioChunk`] directly to avoid the copy.
+	pub fn into_utterance_event(self) -> Option<UtteranceEvent> {
+		match self {
+			Self::AudioChunk(audio) => Some(UtteranceEvent::AudioChunk(Vec::from(audio).into_boxed_slice())),
+			Self::Bookmark { at_millis, mark } => Some(UtteranceEvent::SsmlMark { at_millis, mark }),
+			Self::WordBoundary { from_millis, to_millis, text, .. } => Some(UtteranceEvent::WordBoundary { from_millis, to_millis, text }),
+			Self::SentenceBoundary { from_millis, to_millis, text, .. } => Some(UtteranceEvent::SentenceBoundary { from_millis, to_millis, text }),
+			Self::BlendShapeVisemesChunk(frames) => Some(UtteranceEvent::BlendShapeVisemesChunk(frames)),
+			Self::PunctuationBoundary { .. }
+			| Self::Viseme { .. }
+			| Self::SvgViseme { .. }
+			| Self::SessionEnd { .. }
+			| Self::Metrics(_)
+			| Self::Restarted { .. }
+			| Self::Unknown { .. } => None
+		}
+	}
+}
+
+/// Timing and throughput measurements of a single synthesis turn, reported by [`SynthesisEvent::Metrics`].
+///
+/// Except for [`connect`](Self::connect), durations are measured from when the turn's request was sent on the
+/// connection.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct SynthesisMetrics {
+	/// The ID of the request.
+	pub request_id: String,
+	/// How long it took to open the WebSocket connection and send its `speech.config`, or `None` if the turn reused an
+	/// existing connection.
+	pub connect: Option<Duration>,
+	/// Time until the service acknowledged the request with `turn.start`.
+	pub turn_start: Option<Duration>,
+	/// Time until the first `audio` message was received.
+	pub first_audio: Option<Duration>,
+	/// Time until `turn.end` was received.
+	pub turn_end: Duration,
+	/// The total number of audio bytes received.
+	pub audio_bytes: u64,
+	/// The duration of the audio received, as reported by the service if
+	/// [session end](super::AzureUtteranceConfig::session_end) events were requested, or otherwise estimated from its
+	/// size. `None` for variable bitrate output formats without session end events.
+	pub audio_duration: Option<Duration>
+}
+
+impl SynthesisMetrics {
+	/// Returns the latency until the first audio was received, including the time taken to connect.
+	pub fn time_to_first_audio(&self) -> Option<Duration> {
+		self.first_audio.map(|first_audio| self.connect.unwrap_or_default() + first_audio)
+	}
+}
+
+pub(crate) fn utterance_events(
+	events: impl Stream<Item = crate::Result<SynthesisEvent>> + Send + 'static
+) -> impl Stream<Item = crate::Result<UtteranceEvent>> + Send + 'static {
+	events.filter_map(|event| std::future::ready(event.map(SynthesisEvent::into_utterance_event).transpose()))
+}
@@ -0,0 +1,256 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use speech_synthesis::{AudioChannels, AudioCodec, AudioContainer, AudioEncoding, AudioFormat, AudioFormatPreference};
+
+use crate::Error;
+
+macro_rules! output_formats {
+	($($(#[$meta:meta])* $variant:ident => $name:literal, $container:expr, $sample_rate:literal, $bitrate:expr;)+) => {
+		/// An audio output format supported by the speech service.
+		///
+		/// Formats can be converted to and from Azure's format strings via [`FromStr`] and [`Display`](fmt::Display), and
+		/// to and from [`AudioFormat`]s for use with [`SpeechSynthesiser`](speech_synthesis::SpeechSynthesiser). Not
+		/// every format has an [`AudioFormat`] equivalent; these formats (e.g. AMR-WB or G.722) return `None` from
+		/// [`AzureOutputFormat::container`].
+		///
+		/// All formats are mono.
+		#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+		#[non_exhaustive]
+		pub enum AzureOutputFormat {
+			$($(#[$meta])* $variant),+
+		}
+
+		impl AzureOutputFormat {
+			/// Every output format supported by the speech service.
+			pub const ALL: &'static [AzureOutputFormat] = &[$(AzureOutputFormat::$variant),+];
+
+			/// Returns Azure's name for this format, as used in the `synthesis.context` message or the
+			/// `X-Microsoft-OutputFormat` header.
+			pub fn as_str(&self) -> &'static str {
+				match self {
+					$(Self::$variant => $name),+
+				}
+			}
+
+			/// Returns the container of this format, or `None` if this format can't be represented by an
+			/// [`AudioContainer`].
+			pub fn container(&self) -> Option<AudioContainer> {
+				match self {
+					$(Self::$variant => $container),+
+				}
+			}
+
+			/// Returns the sample rate of this format in Hz.
+			pub fn sample_rate(&self) -> u32 {
+				match self {
+					$(Self::$variant => $sample_rate),+
+				}
+			}
+
+			/// Returns the bitrate of this format in kbps, if it is fixed.
+			pub fn bitrate(&self) -> Option<u16> {
+				match self {
+					$(Self::$variant => $bitrate),+
+				}
+			}
+		}
+	};
+}
+
+output_formats! {
+	Raw8Khz8BitMonoALaw => "raw-8khz-8bit-mono-alaw", Some(AudioContainer::Raw(AudioEncoding::ALaw)), 8_000, None;
+	Raw8Khz8BitMonoMuLaw => "raw-8khz-8bit-mono-mulaw", Some(AudioContainer::Raw(AudioEncoding::MuLaw)), 8_000, None;
+	Raw8Khz16BitMonoPcm => "raw-8khz-16bit-mono-pcm", Some(AudioContainer::Raw(AudioEncoding::PcmI16)), 8_000, None;
+	Raw16Khz16BitMonoPcm => "raw-16khz-16bit-mono-pcm", Some(AudioContainer::Raw(AudioEncoding::PcmI16)), 16_000, None;
+	Raw22050Hz16BitMonoPcm => "raw-22050hz-16bit-mono-pcm", Some(AudioContainer::Raw(AudioEncoding::PcmI16)), 22_050, None;
+	Raw24Khz16BitMonoPcm => "raw-24khz-16bit-mono-pcm", Some(AudioContainer::Raw(AudioEncoding::PcmI16)), 24_000, None;
+	Raw44100Hz16BitMonoPcm => "raw-44100hz-16bit-mono-pcm", Some(AudioContainer::Raw(AudioEncoding::PcmI16)), 44_100, None;
+	Raw48Khz16BitMonoPcm => "raw-48khz-16bit-mono-pcm", Some(AudioContainer::Raw(AudioEncoding::PcmI16)), 48_000, None;
+	Raw16Khz16BitMonoTrueSilk => "raw-16khz-16bit-mono-truesilk", None, 16_000, None;
+	Raw24Khz16BitMonoTrueSilk => "raw-24khz-16bit-mono-truesilk", None, 24_000, None;
+	/// RIFF (WAV) formats include a header, and are therefore not suitable for concatenating streamed chunks.
+	Riff8Khz8BitMonoALaw => "riff-8khz-8bit-mono-alaw", Some(AudioContainer::Riff(AudioEncoding::ALaw)), 8_000, None;
+	Riff8Khz8BitMonoMuLaw => "riff-8khz-8bit-mono-mulaw", Some(AudioContainer::Riff(AudioEncoding::MuLaw)), 8_000, None;
+	Riff8Khz16BitMonoPcm => "riff-8khz-16bit-mono-pcm", Some(AudioContainer::Riff(AudioEncoding::PcmI16)), 8_000, None;
+	Riff16Khz16BitMonoPcm => "riff-16khz-16bit-mono-pcm", Some(AudioContainer::Riff(AudioEncoding::PcmI16)), 16_000, None;
+	Riff22050Hz16BitMonoPcm => "riff-22050hz-16bit-mono-pcm", Some(AudioContainer::Riff(AudioEncoding::PcmI16)), 22_050, None;
+	Riff24Khz16BitMonoPcm => "riff-24khz-16bit-mono-pcm", Some(AudioContainer::Riff(AudioEncoding::PcmI16)), 24_000, None;
+	Riff44100Hz16BitMonoPcm => "riff-44100hz-16bit-mono-pcm", Some(AudioContainer::Riff(AudioEncoding::PcmI16)), 44_100, None;
+	Riff48Khz16BitMonoPcm => "riff-48khz-16bit-mono-pcm", Some(AudioContainer::Riff(AudioEncoding::PcmI16)), 48_000, None;
+	Audio16Khz32KbitrateMonoMp3 => "audio-16khz-32kbitrate-mono-mp3", Some(AudioContainer::Mp3), 16_000, Some(32);
+	Audio16Khz64KbitrateMonoMp3 => "audio-16khz-64kbitrate-mono-mp3", Some(AudioContainer::Mp3), 16_000, Some(64);
+	Audio16Khz128KbitrateMonoMp3 => "audio-16khz-128kbitrate-mono-mp3", Some(AudioContainer::Mp3), 16_000, Some(128);
+	Audio24Khz48KbitrateMonoMp3 => "audio-24khz-48kbitrate-mono-mp3", Some(AudioContainer::Mp3), 24_000, Some(48);
+	Audio24Khz96KbitrateMonoMp3 => "audio-24khz-96kbitrate-mono-mp3", Some(AudioContainer::Mp3), 24_000, Some(96);
+	Audio24Khz160KbitrateMonoMp3 => "audio-24khz-160kbitrate-mono-mp3", Some(AudioContainer::Mp3), 24_000, Some(160);
+	Audio48Khz96KbitrateMonoMp3 => "audio-48khz-96kbitrate-mono-mp3", Some(AudioContainer::Mp3), 48_000, Some(96);
+	Audio48Khz192KbitrateMonoMp3 => "audio-48khz-192kbitrate-mono-mp3", Some(AudioContainer::Mp3), 48_000, Some(192);
+	Ogg16Khz16BitMonoOpus => "ogg-16khz-16bit-mono-opus", Some(AudioContainer::Ogg(AudioCodec::Opus)), 16_000, None;
+	Ogg24Khz16BitMonoOpus => "ogg-24khz-16bit-mono-opus", Some(AudioContainer::Ogg(AudioCodec::Opus)), 24_000, None;
+	Ogg48Khz16BitMonoOpus => "ogg-48khz-16bit-mono-opus", Some(AudioContainer::Ogg(AudioCodec::Opus)), 48_000, None;
+	Webm16Khz16BitMonoOpus => "webm-16khz-16bit-mono-opus", Some(AudioContainer::Webm(AudioCodec::Opus)), 16_000, None;
+	Webm24Khz16BitMonoOpus => "webm-24khz-16bit-mono-opus", Some(AudioContainer::Webm(AudioCodec::Opus)), 24_000, None;
+	Webm24Khz16Bit24KbpsMonoOpus => "webm-24khz-16bit-24kbps-mono-opus", Some(AudioContainer::Webm(AudioCodec::Opus)), 24_000, Some(24);
+	/// Containerless Opus frames.
+	Audio16Khz16Bit32KbpsMonoOpus => "audio-16khz-16bit-32kbps-mono-opus", None, 16_000, Some(32);
+	Audio24Khz16Bit24KbpsMonoOpus => "audio-24khz-16bit-24kbps-mono-opus", None, 24_000, Some(24);
+	Audio24Khz16Bit48KbpsMonoOpus => "audio-24khz-16bit-48kbps-mono-opus", None, 24_000, Some(48);
+	AmrWb16000Hz => "amr-wb-16000hz", None, 16_000, None;
+	G722Mono16Khz64Kbps => "g722-16khz-64kbps", None, 16_000, Some(64);
+}
+
+/// Returns the size of one sample of an uncompressed encoding.
+fn bytes_per_sample(encoding: AudioEncoding) -> Option<u32> {
+	match encoding {
+		AudioEncoding::ALaw | AudioEncoding::MuLaw => Some(1),
+		AudioEncoding::PcmI16 => Some(2),
+		_ => None
+	}
+}
+
+/// Returns the option closest to `target`, preferring the higher option on a tie.
+fn closest(target: u16, options: impl IntoIterator<Item = u16>) -> Option<u16> {
+	options
+		.into_iter()
+		.min_by_key(|&option| ((option as i32 - target as i32).abs(), std::cmp::Reverse(option)))
+}
+
+impl AzureOutputFormat {
+	/// Estimates the duration of `bytes` bytes of audio in this format, for uncompressed and constant bitrate formats.
+	pub(crate) fn audio_duration(&self, bytes: u64) -> Option<Duration> {
+		let (bytes_per_second, header) = match (self.container(), self.bitrate()) {
+			(Some(AudioContainer::Raw(encoding)), _) => (bytes_per_sample(encoding)? * self.sample_rate(), 0),
+			(Some(AudioContainer::Riff(encoding)), _) => (bytes_per_sample(encoding)? * self.sample_rate(), 44),
+			(_, Some(kbps)) => (u32::from(kbps) * 1000 / 8, 0),
+			_ => return None
+		};
+		Some(Duration::from_secs_f64(bytes.saturating_sub(header) as f64 / f64::from(bytes_per_second)))
+	}
+
+	/// Returns the [`AudioFormat`] equivalent of this format, if there is one.
+	pub fn to_audio_format(&self) -> Option<AudioFormat> {
+		Some(AudioFormat::new(self.sample_rate(), AudioChannels::Mono, self.bitrate(), self.container()?))
+	}
+
+	/// Returns the output format matching the given [`AudioFormat`], if the speech service supports it.
+	///
+	/// If the audio format has a bitrate, the closest supported bitrate is chosen. Without a bitrate, the service's
+	/// default bitrate (or else the highest bitrate) is chosen.
+	pub fn from_audio_format(format: &AudioFormat) -> Option<Self> {
+		if format.channels() != AudioChannels::Mono {
+			return None;
+		}
+		Self::select(format.container(), format.sample_rate(), format.bitrate())
+	}
+
+	fn select(container: AudioContainer, sample_rate: u32, bitrate: Option<u16>) -> Option<Self> {
+		let candidates = || {
+			Self::ALL
+				.iter()
+				.copied()
+				.filter(move |f| f.container() == Some(container) && f.sample_rate() == sample_rate)
+		};
+		if let Some(bitrate) = bitrate.and_then(|b| closest(b, candidates().filter_map(|f| f.bitrate()))) {
+			return candidates().find(|f| f.bitrate() == Some(bitrate));
+		}
+		candidates()
+			.find(|f| f.bitrate().is_none())
+			.or_else(|| candidates().max_by_key(|f| f.bitrate()))
+	}
+
+	/// Negotiates the best output format for the given preference. See
+	/// [`SpeechSynthesiser::negotiate_audio_format`](speech_synthesis::SpeechSynthesiser::negotiate_audio_format).
+	pub fn negotiate(pref: &AudioFormatPreference) -> Option<Self> {
+		if pref.channels.as_ref().is_some_and(|channels| !channels.contains(&AudioChannels::Mono)) {
+			return None;
+		}
+
+		let Some(containers) = pref.containers.as_ref() else {
+			return Some(Self::Raw48Khz16BitMonoPcm);
+		};
+		// If multiple bitrates are acceptable, aim for the highest quality one.
+		let bitrate = pref.bitrates.as_ref().and_then(|bitrates| bitrates.iter().copied().max());
+		for container in containers {
+			let mut sample_rates: Vec<u32> = Self::ALL
+				.iter()
+				.filter(|f| f.container() == Some(*container))
+				.map(|f| f.sample_rate())
+				.collect();
+			sample_rates.sort_by(|a, b| b.cmp(a));
+			sample_rates.dedup();
+
+			let sample_rate = match pref.sample_rates.as_ref() {
+				Some(preferred) => {
+					let mut preferred = preferred.clone();
+					preferred.sort_by(|a, b| b.cmp(a));
+					preferred.into_iter().find(|sr| sample_rates.contains(sr))
+				}
+				None => sample_rates.first().copied()
+			};
+			if let Some(format) = sample_rate.and_then(|sample_rate| Self::select(*container, sample_rate, bitrate)) {
+				return Some(format);
+			}
+		}
+		None
+	}
+}
+
+impl fmt::Display for AzureOutputFormat {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+impl FromStr for AzureOutputFormat {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Self::ALL
+			.iter()
+			.copied()
+			.find(|f| f.as_str().eq_ignore_ascii_case(s))
+			.ok_or_else(|| Error::UnknownOutputFormat(s.to_string()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_round_trip() -> crate::Result<()> {
+		for format in AzureOutputFormat::ALL {
+			assert_eq!(&format.as_str().parse::<AzureOutputFormat>()?, format);
+			if let Some(audio_format) = format.to_audio_format() {
+				assert_eq!(AzureOutputFormat::from_audio_format(&audio_format).as_ref(), Some(format));
+			}
+		}
+		assert!("riff-8khz-16bit-stereo-pcm".parse::<AzureOutputFormat>().is_err());
+		Ok(())
+	}
+
+	#[test]
+	fn test_negotiate_bitrate() {
+		let pref = AudioFormatPreference::default()
+			.with_prefer_containers([AudioContainer::Mp3])
+			.with_prefer_sample_rates([24_000])
+			.with_prefer_bitrates([128]);
+		assert_eq!(AzureOutputFormat::negotiate(&pref), Some(AzureOutputFormat::Audio24Khz160KbitrateMonoMp3));
+
+		let pref = AudioFormatPreference::default()
+			.with_prefer_containers([AudioContainer::Riff(AudioEncoding::MuLaw)])
+			.with_prefer_sample_rates([16_000]);
+		assert_eq!(AzureOutputFormat::negotiate(&pref), None);
+
+		let pref = AudioFormatPreference::default().with_prefer_containers([AudioContainer::Mp3]);
+		assert_eq!(AzureOutputFormat::negotiate(&pref), Some(AzureOutputFormat::Audio48Khz192KbitrateMonoMp3));
+	}
+
+	#[test]
+	fn test_audio_duration() {
+		assert_eq!(AzureOutputFormat::Raw24Khz16BitMonoPcm.audio_duration(48_000), Some(Duration::from_secs(1)));
+		assert_eq!(AzureOutputFormat::Riff8Khz8BitMonoMuLaw.audio_duration(8_044), Some(Duration::from_secs(1)));
+		assert_eq!(AzureOutputFormat::Audio16Khz32KbitrateMonoMp3.audio_duration(8_000), Some(Duration::from_secs(2)));
+		assert_eq!(AzureOutputFormat::Ogg24Khz16BitMonoOpus.audio_duration(8_000), None);
+	}
+}
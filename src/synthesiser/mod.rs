@@ -1,199 +1,224 @@
-use futures_util::{SinkExt, Stream};
-use http::{HeaderName, HeaderValue};
-use speech_synthesis::{AudioChannels, AudioCodec, AudioContainer, AudioEncoding, AudioFormat, SpeechSynthesiser, UtteranceConfig, UtteranceEvent};
+use std::sync::Arc;
+
+use futures_util::Stream;
+use http::{HeaderName, HeaderValue, Uri};
+use speech_synthesis::{AudioFormat, SpeechSynthesiser, UtteranceConfig, UtteranceEvent};
 use ssml::{Serialize, SerializeOptions};
-use tokio_websockets::ClientBuilder;
 
+mod builder;
+mod connection;
+mod event;
+mod format;
+mod pool;
+mod retry;
+mod session;
 mod stream;
-use super::message::AzureCognitiveSpeechServicesMessage;
-use crate::Error;
+mod text;
+mod timeouts;
+mod utterance;
+mod viseme;
+pub use self::{
+	builder::{AzureCloud, AzureCognitiveSpeechServicesSynthesiserBuilder},
+	event::{SynthesisEvent, SynthesisMetrics},
+	format::AzureOutputFormat,
+	pool::PoolConfig,
+	retry::RetryPolicy,
+	session::SynthesisSession,
+	text::SsmlTextMap,
+	timeouts::Timeouts,
+	utterance::AzureUtteranceConfig,
+	viseme::{SvgVisemeAnimation, VISEME_PHONEMES, VisemeType, viseme_phonemes}
+};
+use self::{
+	connection::Connection,
+	pool::{ConnectionPool, MaybePooled},
+	text::TextLocator
+};
+use super::message::{AzureCognitiveSpeechServicesMessage, MetadataOptions, SpeechConfigContext};
+use crate::{
+	Error,
+	auth::Credential,
+	recording::Recorder,
+	transport::{ConnectRequest, Transport}
+};
 
 #[derive(Clone)]
 pub struct AzureCognitiveSpeechServicesSynthesiser {
-	endpoint: String,
-	key: HeaderValue
+	endpoint: Uri,
+	credential: Arc<dyn Credential>,
+	headers: Arc<[(HeaderName, HeaderValue)]>,
+	pool: Option<Arc<ConnectionPool>>,
+	retry_policy: RetryPolicy,
+	timeouts: Timeouts,
+	recorder: Option<Recorder>,
+	transport: Arc<dyn Transport>,
+	speech_config_context: Arc<SpeechConfigContext>
 }
 
 unsafe impl Sync for AzureCognitiveSpeechServicesSynthesiser {}
 unsafe impl Send for AzureCognitiveSpeechServicesSynthesiser {}
 
 impl AzureCognitiveSpeechServicesSynthesiser {
-	pub fn new(region: impl AsRef<str>, key: impl AsRef<str>) -> Self {
-		Self {
-			endpoint: format!("wss://{}.tts.speech.microsoft.com/cognitiveservices/websocket/v1", region.as_ref()),
-			key: HeaderValue::from_str(key.as_ref()).expect("invalid key")
+	/// Creates a synthesiser for the given region which authenticates with a subscription key.
+	///
+	/// For more configuration options, see [`AzureCognitiveSpeechServicesSynthesiser::builder`].
+	pub fn new(region: impl Into<String>, key: impl Into<String>) -> crate::Result<Self> {
+		Self::builder().with_region(region).with_subscription_key(key).build()
+	}
+
+	/// Creates a synthesiser for the given region which authenticates with the given [`Credential`], e.g. an
+	/// [`IssuedToken`](crate::auth::IssuedToken) to avoid sending the subscription key on every connection.
+	pub fn from_credential(region: impl Into<String>, credential: impl Credential) -> crate::Result<Self> {
+		Self::builder().with_region(region).with_credential(credential).build()
+	}
+
+	pub fn builder() -> AzureCognitiveSpeechServicesSynthesiserBuilder {
+		AzureCognitiveSpeechServicesSynthesiserBuilder::new()
+	}
+
+	/// Opens [`PoolConfig::warm_connections`] connections ahead of time, so the first utterances don't have to wait for
+	/// the handshake. Connections the service has since closed are replaced.
+	///
+	/// Does nothing if the synthesiser was not configured with a pool via
+	/// [`AzureCognitiveSpeechServicesSynthesiserBuilder::with_connection_pool`].
+	pub async fn warm_connection_pool(&self) -> crate::Result<()> {
+		match self.pool.as_ref() {
+			Some(pool) => pool.warm(self).await,
+			None => Ok(())
 		}
 	}
 
-	fn build_client(&self) -> crate::Result<ClientBuilder> {
-		Ok(ClientBuilder::new()
-			.uri(self.endpoint.as_str())
-			.unwrap()
-			.add_header(HeaderName::from_static("ocp-apim-subscription-key"), self.key.clone()))
-	}
-
-	fn name_for_format(format: &AudioFormat) -> Option<&str> {
-		match (format.container(), format.sample_rate(), format.channels()) {
-			(AudioContainer::Raw(AudioEncoding::ALaw), 8000, AudioChannels::Mono) => Some("raw-8khz-8bit-mono-alaw"),
-			(AudioContainer::Raw(AudioEncoding::MuLaw), 8000, AudioChannels::Mono) => Some("raw-8khz-8bit-mono-mulaw"),
-			(AudioContainer::Raw(AudioEncoding::PcmI16), 8000, AudioChannels::Mono) => Some("raw-8khz-16bit-mono-pcm"),
-			(AudioContainer::Raw(AudioEncoding::PcmI16), 16000, AudioChannels::Mono) => Some("raw-16khz-16bit-mono-pcm"),
-			(AudioContainer::Raw(AudioEncoding::PcmI16), 22050, AudioChannels::Mono) => Some("raw-22050khz-16bit-mono-pcm"),
-			(AudioContainer::Raw(AudioEncoding::PcmI16), 24000, AudioChannels::Mono) => Some("raw-24khz-16bit-mono-pcm"),
-			(AudioContainer::Raw(AudioEncoding::PcmI16), 44100, AudioChannels::Mono) => Some("raw-44100khz-16bit-mono-pcm"),
-			(AudioContainer::Raw(AudioEncoding::PcmI16), 48000, AudioChannels::Mono) => Some("raw-48khz-16bit-mono-pcm"),
-			(AudioContainer::Ogg(AudioCodec::Opus), 16000, AudioChannels::Mono) => Some("ogg-16khz-16bit-mono-opus"),
-			(AudioContainer::Ogg(AudioCodec::Opus), 24000, AudioChannels::Mono) => Some("ogg-24khz-16bit-mono-opus"),
-			(AudioContainer::Ogg(AudioCodec::Opus), 48000, AudioChannels::Mono) => Some("ogg-48khz-16bit-mono-opus"),
-			_ => None
+	async fn connect_request(&self) -> crate::Result<ConnectRequest> {
+		let mut headers = self.headers.to_vec();
+		headers.extend(self.credential.headers().await?);
+		Ok(ConnectRequest { uri: self.endpoint.clone(), headers })
+	}
+
+	fn output_format(audio_format: &AudioFormat) -> crate::Result<AzureOutputFormat> {
+		AzureOutputFormat::from_audio_format(audio_format).ok_or(Error::UnsupportedAudioFormat)
+	}
+
+	fn ssml_for_speak(input: &ssml::Speak<'_>, config: &AzureUtteranceConfig) -> crate::Result<String> {
+		let options = SerializeOptions::default().flavor(ssml::Flavor::MicrosoftAzureCognitiveSpeechServices);
+		if !config.visemes || config.viseme_type == VisemeType::Id {
+			return Ok(input.serialize_to_string(&options)?);
 		}
+		let mut speak = input.clone();
+		self::viseme::request_viseme_type(&mut speak, config.viseme_type, config.utterance.voice.as_deref())?;
+		Ok(speak.serialize_to_string(&options)?)
+	}
+
+	fn ssml_for_text(input: &str, config: &AzureUtteranceConfig) -> crate::Result<String> {
+		let voice = config.utterance.voice.as_deref();
+		let speak = ssml::Speak::new::<ssml::Element, _>(
+			voice,
+			[match voice {
+				Some(voice) => ssml::voice(voice, [input.to_string()]).into(),
+				None => input.to_string().into()
+			}]
+		);
+		Self::ssml_for_speak(&speak, config)
 	}
 
-	async fn speak_inner(
+	/// Stream the synthesis of an [`ssml`] document, including Azure-specific events which have no [`UtteranceEvent`]
+	/// equivalent.
+	///
+	/// See [`SpeechSynthesiser::synthesise_ssml_stream`].
+	pub async fn synthesise_ssml_events(
 		&self,
-		ssml_string: String,
+		input: &ssml::Speak<'_>,
 		audio_format: &AudioFormat,
-		config: &UtteranceConfig
-	) -> crate::Result<impl Stream<Item = crate::Result<UtteranceEvent>> + Send + 'static> {
-		let client_builder = self.build_client()?;
-		let (mut websocket, _response) = client_builder.connect().await?;
-		websocket
-			.send(
-				AzureCognitiveSpeechServicesMessage::builder("speech.config", AzureCognitiveSpeechServicesMessage::gen_request_id())
-					.with_content_type(AzureCognitiveSpeechServicesMessage::CONTENT_TYPE_JSON)
-					.with_body(
-						r#"{"context":{"system":{"version":"1.30.0","name":"SpeechSDK","build":"Windows-x64"},"os":{"platform":"Windows","name":"Client","version":"10"}}}"#
-					)
-					.build()?
-					.into_websocket_message()
-			)
-			.await?;
+		config: impl Into<AzureUtteranceConfig>
+	) -> crate::Result<impl Stream<Item = crate::Result<SynthesisEvent>> + Send + 'static> {
+		self.ssml_events(input, audio_format, &config.into(), true).await
+	}
 
-		let request_id = AzureCognitiveSpeechServicesMessage::gen_request_id();
+	async fn ssml_events(
+		&self,
+		input: &ssml::Speak<'_>,
+		audio_format: &AudioFormat,
+		config: &AzureUtteranceConfig,
+		restartable: bool
+	) -> crate::Result<impl Stream<Item = crate::Result<SynthesisEvent>> + Send + 'static> {
+		let output_format = Self::output_format(audio_format)?;
+		self.speak_inner(SsmlTextMap::new(Self::ssml_for_speak(input, config)?), false, output_format, config.metadata_options(), restartable)
+			.await
+	}
 
-		websocket
-			.send(
-				AzureCognitiveSpeechServicesMessage::builder("synthesis.context", &request_id)
-					.with_content_type(AzureCognitiveSpeechServicesMessage::CONTENT_TYPE_JSON)
-					.with_body(format!(
-						r#"{{"synthesis":{{"audio":{{"metadataOptions":{{"sentenceBoundaryEnabled":{},"wordBoundaryEnabled":{},"bookmarkEnabled":true,"sessionEndEnabled":false}},"outputFormat":"{}"}}}}}}"#,
-						config.emit_sentence_boundary_events,
-						config.emit_word_boundary_events,
-						Self::name_for_format(audio_format).ok_or(Error::UnsupportedAudioFormat)?
-					))
-					.build()?
-					.into_websocket_message()
-			)
-			.await?;
-		websocket
-			.send(
-				AzureCognitiveSpeechServicesMessage::builder("ssml", &request_id)
-					.with_content_type(AzureCognitiveSpeechServicesMessage::CONTENT_TYPE_SSML)
-					.with_body(ssml_string)
-					.build()?
-					.into_websocket_message()
-			)
-			.await?;
-
-		Ok(self::stream::stream(request_id, websocket))
+	/// Stream the synthesis of raw text, including Azure-specific events which have no [`UtteranceEvent`] equivalent.
+	///
+	/// See [`SpeechSynthesiser::synthesise_text_stream`].
+	pub async fn synthesise_text_events(
+		&self,
+		input: &str,
+		audio_format: &AudioFormat,
+		config: impl Into<AzureUtteranceConfig>
+	) -> crate::Result<impl Stream<Item = crate::Result<SynthesisEvent>> + Send + 'static> {
+		self.text_events(input, audio_format, &config.into(), true).await
 	}
-}
 
-impl SpeechSynthesiser for AzureCognitiveSpeechServicesSynthesiser {
-	type Error = crate::Error;
+	async fn text_events(
+		&self,
+		input: &str,
+		audio_format: &AudioFormat,
+		config: &AzureUtteranceConfig,
+		restartable: bool
+	) -> crate::Result<impl Stream<Item = crate::Result<SynthesisEvent>> + Send + 'static> {
+		let output_format = Self::output_format(audio_format)?;
+		self.speak_inner(SsmlTextMap::new(Self::ssml_for_text(input, config)?), true, output_format, config.metadata_options(), restartable)
+			.await
+	}
 
-	fn negotiate_audio_format(&self, pref: &speech_synthesis::AudioFormatPreference) -> Option<AudioFormat> {
-		#[allow(unused)]
-		fn optimal(input: u32, options: &[u32]) -> u32 {
-			let mut closest_match = None;
-			let mut closest_difference = i32::MAX;
-
-			for &option in options {
-				let difference = (input as i32 - option as i32).abs();
-				if difference < closest_difference || (difference == closest_difference && option > closest_match.unwrap_or(u32::MIN)) {
-					closest_match = Some(option);
-					closest_difference = difference;
-				}
-			}
+	/// Creates a [`SynthesisSession`] which reuses a single connection for all of its utterances.
+	pub fn session(&self) -> SynthesisSession {
+		SynthesisSession::new(self.clone())
+	}
 
-			closest_match.unwrap()
-		}
+	pub(crate) async fn speak_inner(
+		&self,
+		ssml: SsmlTextMap,
+		plain_text: bool,
+		output_format: AzureOutputFormat,
+		metadata_options: MetadataOptions,
+		restartable: bool
+	) -> crate::Result<impl Stream<Item = crate::Result<SynthesisEvent>> + Send + 'static> {
+		let (synthesiser, ssml) = (self.clone(), Arc::new(ssml));
+		self::retry::retrying(self.retry_policy.clone(), restartable, move || {
+			let (synthesiser, ssml, metadata_options) = (synthesiser.clone(), Arc::clone(&ssml), metadata_options.clone());
+			async move { synthesiser.start_turn(&ssml, plain_text, output_format, &metadata_options).await }
+		})
+		.await
+	}
 
-		let sample_rates = pref.sample_rates.clone().map(|mut f| {
-			f.sort_by(|a, b| b.cmp(a));
-			f
-		});
-		let bitrates = pref.bitrates.clone().map(|mut f| {
-			f.sort_by(|a, b| b.cmp(a));
-			f
-		});
-		let channels = pref.channels.clone();
-
-		fn match_container(
-			container: &AudioContainer,
-			sample_rates: Option<&Vec<u32>>,
-			#[allow(unused)] bitrates: Option<&Vec<u16>>,
-			channels: Option<&Vec<AudioChannels>>
-		) -> Option<AudioFormat> {
-			match container {
-				AudioContainer::Raw(encoding) => {
-					if channels.is_some() && !channels.unwrap().iter().any(|c| c == &AudioChannels::Mono) {
-						None
-					} else {
-						let sample_rate = sample_rates
-							.map(|sr_prefs| {
-								for sr in sr_prefs.iter().copied() {
-									match (sr, encoding) {
-										(8_000, AudioEncoding::ALaw) => return Some(sr),
-										(8_000, AudioEncoding::MuLaw) => return Some(sr),
-										(8_000, AudioEncoding::PcmI16) => return Some(sr),
-										(16_000, AudioEncoding::PcmI16) => return Some(sr),
-										(22_050, AudioEncoding::PcmI16) => return Some(sr),
-										(24_000, AudioEncoding::PcmI16) => return Some(sr),
-										(44_100, AudioEncoding::PcmI16) => return Some(sr),
-										(48_000, AudioEncoding::PcmI16) => return Some(sr),
-										_ => continue
-									};
-								}
-								None
-							})
-							.unwrap_or(Some(48_000))?;
-						Some(AudioFormat::new(sample_rate, AudioChannels::Mono, None, *container))
-					}
-				}
-				AudioContainer::Ogg(AudioCodec::Opus) => {
-					if channels.is_some() && !channels.unwrap().iter().any(|c| c == &AudioChannels::Mono) {
-						None
-					} else {
-						let sample_rate = sample_rates
-							.map(|sr_prefs| {
-								for sr in sr_prefs.iter().copied() {
-									match sr {
-										16_000 | 24_000 | 48_000 => return Some(sr),
-										_ => continue
-									};
-								}
-								None
-							})
-							.unwrap_or(Some(48_000))?;
-						Some(AudioFormat::new(sample_rate, AudioChannels::Mono, None, *container))
-					}
-				}
-				// TODO: other formats
-				_ => None
+	/// Makes a single attempt at starting a turn, on a pooled or dedicated connection.
+	async fn start_turn(
+		&self,
+		ssml: &Arc<SsmlTextMap>,
+		plain_text: bool,
+		output_format: AzureOutputFormat,
+		metadata_options: &MetadataOptions
+	) -> crate::Result<impl Stream<Item = crate::Result<SynthesisEvent>> + Send + 'static> {
+		let request_id = AzureCognitiveSpeechServicesMessage::gen_request_id();
+		let (connection, turn) = match self.pool.as_ref() {
+			Some(pool) => {
+				let (connection, turn) = pool.start_turn(self, &request_id, ssml.ssml(), output_format, metadata_options).await?;
+				(MaybePooled::Pooled(connection), turn)
 			}
-		}
-
-		if let Some(containers) = pref.containers.as_ref() {
-			for container in containers {
-				if let Some(format) = match_container(container, sample_rates.as_ref(), bitrates.as_ref(), channels.as_ref()) {
-					return Some(format);
-				}
+			None => {
+				let mut connection = Connection::open(self).await.map_err(|e| e.with_request_id(&request_id))?;
+				let turn = connection.start_turn(&request_id, ssml.ssml(), output_format, metadata_options).await?;
+				(MaybePooled::Dedicated(Box::new(connection)), turn)
 			}
-			None
-		} else {
-			Some(AudioFormat::new(48_000, AudioChannels::Mono, None, AudioContainer::Raw(AudioEncoding::PcmI16)))
-		}
+		};
+		Ok(self::stream::stream(request_id, connection, turn, self.timeouts, TextLocator::new(Arc::clone(ssml), plain_text)))
+	}
+}
+
+impl SpeechSynthesiser for AzureCognitiveSpeechServicesSynthesiser {
+	type Error = crate::Error;
+
+	fn negotiate_audio_format(&self, pref: &speech_synthesis::AudioFormatPreference) -> Option<AudioFormat> {
+		AzureOutputFormat::negotiate(pref).and_then(|format| format.to_audio_format())
 	}
 
 	async fn synthesise_ssml_stream(
@@ -202,8 +227,8 @@ impl SpeechSynthesiser for AzureCognitiveSpeechServicesSynthesiser {
 		audio_format: &AudioFormat,
 		config: &UtteranceConfig
 	) -> Result<impl Stream<Item = crate::Result<UtteranceEvent>> + Send + 'static, Self::Error> {
-		let ssml_string = input.serialize_to_string(&SerializeOptions::default().flavor(ssml::Flavor::MicrosoftAzureCognitiveSpeechServices))?;
-		self.speak_inner(ssml_string, audio_format, config).await
+		// `UtteranceEvent` has no equivalent of `SynthesisEvent::Restarted`, so never restart once events were yielded.
+		Ok(self::event::utterance_events(self.ssml_events(input, audio_format, &AzureUtteranceConfig::from(config), false).await?))
 	}
 
 	async fn synthesise_text_stream(
@@ -212,12 +237,7 @@ impl SpeechSynthesiser for AzureCognitiveSpeechServicesSynthesiser {
 		audio_format: &AudioFormat,
 		config: &UtteranceConfig
 	) -> Result<impl speech_synthesis::UtteranceEventStream<Self::Error> + 'static, Self::Error> {
-		let ssml_string = ssml::Speak::new::<ssml::Element, _>(config.voice.as_deref(), [match config.voice.as_ref() {
-			Some(voice) => ssml::voice(voice.as_ref(), [input.to_string()]).into(),
-			None => input.to_string().into()
-		}])
-		.serialize_to_string(&SerializeOptions::default().flavor(ssml::Flavor::MicrosoftAzureCognitiveSpeechServices))?;
-		self.speak_inner(ssml_string, audio_format, config).await
+		Ok(self::event::utterance_events(self.text_events(input, audio_format, &AzureUtteranceConfig::from(config), false).await?))
 	}
 }
 
@@ -229,7 +249,7 @@ mod tests {
 
 	#[test]
 	fn test_pref() -> crate::Result<()> {
-		let synthesiser = AzureCognitiveSpeechServicesSynthesiser::new("dummy", "dummy");
+		let synthesiser = AzureCognitiveSpeechServicesSynthesiser::new("dummy", "dummy")?;
 		let pref = AudioFormatPreference::default()
 			.with_prefer_containers([AudioContainer::Raw(AudioEncoding::PcmI16), AudioContainer::Ogg(AudioCodec::Opus)])
 			.with_prefer_channels([AudioChannels::Mono])
@@ -0,0 +1,262 @@
+use std::{
+	ops::{Deref, DerefMut},
+	sync::Arc,
+	time::Duration
+};
+
+use futures_util::future::try_join_all;
+use tokio::sync::{Mutex, OwnedMappedMutexGuard, OwnedMutexGuard, OwnedSemaphorePermit, Semaphore};
+
+use super::{
+	AzureCognitiveSpeechServicesSynthesiser, AzureOutputFormat,
+	connection::{self, Connection, Turn}
+};
+use crate::message::MetadataOptions;
+
+/// Configuration for a synthesiser's connection pool.
+///
+/// See [`with_connection_pool`](crate::AzureCognitiveSpeechServicesSynthesiserBuilder::with_connection_pool).
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct PoolConfig {
+	/// The maximum number of connections the pool will open, which is also the maximum number of utterances that can be
+	/// synthesised concurrently. Further requests wait until a connection is returned to the pool.
+	pub max_connections: usize,
+	/// The number of connections to open ahead of time with
+	/// [`AzureCognitiveSpeechServicesSynthesiser::warm_connection_pool`].
+	pub warm_connections: usize,
+	/// How long a connection may sit idle in the pool before it is discarded instead of reused. The service closes idle
+	/// connections on its end after a few minutes.
+	pub idle_timeout: Duration
+}
+
+impl Default for PoolConfig {
+	fn default() -> Self {
+		Self {
+			max_connections: 8,
+			warm_connections: 1,
+			idle_timeout: Duration::from_secs(180)
+		}
+	}
+}
+
+impl PoolConfig {
+	/// Configures the maximum number of connections the pool will open.
+	pub fn with_max_connections(mut self, x: usize) -> Self {
+		self.max_connections = x.max(1);
+		self
+	}
+
+	/// Configures the number of connections to open ahead of time.
+	pub fn with_warm_connections(mut self, x: usize) -> Self {
+		self.warm_connections = x;
+		self
+	}
+
+	/// Configures how long a connection may sit idle before it is discarded.
+	pub fn with_idle_timeout(mut self, x: Duration) -> Self {
+		self.idle_timeout = x;
+		self
+	}
+}
+
+type Slot = Arc<Mutex<Option<Connection>>>;
+
+pub(crate) struct ConnectionPool {
+	slots: Box<[Slot]>,
+	permits: Arc<Semaphore>,
+	config: PoolConfig
+}
+
+impl ConnectionPool {
+	pub fn new(config: PoolConfig) -> Self {
+		let max_connections = config.max_connections.max(1);
+		Self {
+			slots: (0..max_connections).map(|_| Slot::default()).collect(),
+			permits: Arc::new(Semaphore::new(max_connections)),
+			config
+		}
+	}
+
+	async fn acquire(&self) -> (OwnedMutexGuard<Option<Connection>>, OwnedSemaphorePermit) {
+		let permit = Arc::clone(&self.permits).acquire_owned().await.expect("pool semaphore is never closed");
+
+		// Every slot is only ever locked while holding a permit, so there is always at least one free slot here. Prefer
+		// one that already has a connection.
+		let mut empty_slot = None;
+		for slot in self.slots.iter() {
+			if let Ok(mut slot) = Arc::clone(slot).try_lock_owned() {
+				if slot
+					.as_ref()
+					.and_then(Connection::idle_duration)
+					.is_some_and(|idle| idle >= self.config.idle_timeout)
+				{
+					*slot = None;
+				}
+				if slot.is_some() {
+					return (slot, permit);
+				} else if empty_slot.is_none() {
+					empty_slot = Some(slot);
+				}
+			}
+		}
+		let slot = match empty_slot {
+			Some(slot) => slot,
+			None => Arc::clone(&self.slots[0]).lock_owned().await
+		};
+		(slot, permit)
+	}
+
+	/// Opens connections until at least [`PoolConfig::warm_connections`] healthy connections are idle in the pool.
+	pub async fn warm(&self, synthesiser: &AzureCognitiveSpeechServicesSynthesiser) -> crate::Result<()> {
+		let warm_slots = &self.slots[..self.config.warm_connections.min(self.slots.len())];
+		try_join_all(warm_slots.iter().map(|slot| async move {
+			let _permit = Arc::clone(&self.permits).acquire_owned().await.expect("pool semaphore is never closed");
+			let mut slot = slot.lock().await;
+			if !slot
+				.as_mut()
+				.is_some_and(|connection| connection.is_reusable() && connection.idle_duration().is_some_and(|idle| idle < self.config.idle_timeout))
+			{
+				*slot = Some(Connection::open(synthesiser).await?);
+			}
+			Ok::<_, crate::Error>(())
+		}))
+		.await?;
+		Ok(())
+	}
+
+	pub async fn start_turn(
+		&self,
+		synthesiser: &AzureCognitiveSpeechServicesSynthesiser,
+		request_id: &str,
+		ssml_string: &str,
+		output_format: AzureOutputFormat,
+		metadata_options: &MetadataOptions
+	) -> crate::Result<(PooledConnection, Turn)> {
+		let (slot, permit) = self.acquire().await;
+		let (connection, turn) = connection::start_turn_in_slot(synthesiser, slot, request_id, ssml_string, output_format, metadata_options).await?;
+		Ok((PooledConnection { connection, _permit: permit }, turn))
+	}
+}
+
+/// A connection checked out from a [`ConnectionPool`]. It is returned to the pool when dropped.
+pub(crate) struct PooledConnection {
+	// Field order matters here: the slot must be unlocked before its permit is released.
+	connection: OwnedMappedMutexGuard<Option<Connection>, Connection>,
+	_permit: OwnedSemaphorePermit
+}
+
+impl Deref for PooledConnection {
+	type Target = Connection;
+
+	fn deref(&self) -> &Self::Target {
+		&self.connection
+	}
+}
+
+impl DerefMut for PooledConnection {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.connection
+	}
+}
+
+/// Either a connection checked out from the synthesiser's pool, or a dedicated connection for a single turn if the
+/// synthesiser has no pool.
+pub(crate) enum MaybePooled {
+	Dedicated(Box<Connection>),
+	Pooled(PooledConnection)
+}
+
+impl Deref for MaybePooled {
+	type Target = Connection;
+
+	fn deref(&self) -> &Self::Target {
+		match self {
+			Self::Dedicated(connection) => connection,
+			Self::Pooled(connection) => connection
+		}
+	}
+}
+
+impl DerefMut for MaybePooled {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		match self {
+			Self::Dedicated(connection) => connection,
+			Self::Pooled(connection) => connection
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Instant;
+
+	use speech_synthesis::UtteranceConfig;
+
+	use super::*;
+	use crate::testing::{self, MockServer, MockTurn};
+
+	#[tokio::test]
+	async fn test_pool_max_connections() -> crate::Result<()> {
+		let turn = || MockTurn::new().with_turn_start().with_delay(Duration::from_millis(100)).with_turn_end();
+		let server = MockServer::start_with([turn(), turn()]).await?;
+		let synthesiser = server
+			.synthesiser_builder()
+			.with_connection_pool(PoolConfig::default().with_max_connections(1))
+			.build()?;
+		let started = Instant::now();
+		let config = UtteranceConfig::default();
+		let (a, b) = tokio::join!(testing::synthesise(&synthesiser, &config), testing::synthesise(&synthesiser, &config));
+		a?;
+		b?;
+		assert!(started.elapsed() >= Duration::from_millis(200));
+		assert_eq!(server.connections(), 1);
+		assert_eq!(server.requests().len(), 2);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_pool_warm() -> crate::Result<()> {
+		let server = MockServer::start().await?;
+		let synthesiser = server
+			.synthesiser_builder()
+			.with_connection_pool(PoolConfig::default().with_warm_connections(2))
+			.build()?;
+		synthesiser.warm_connection_pool().await?;
+		assert_eq!(server.connections(), 2);
+
+		// Warming an already warm pool, or synthesising on it, opens no further connections.
+		synthesiser.warm_connection_pool().await?;
+		testing::synthesise(&synthesiser, &UtteranceConfig::default()).await?;
+		assert_eq!(server.connections(), 2);
+		assert_eq!(server.speech_configs().len(), 2);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_pool_replaces_connections() -> crate::Result<()> {
+		let server = MockServer::start_with([MockTurn::default().with_delay(Duration::from_millis(20)).with_close(1000, "Idle timeout")]).await?;
+		let synthesiser = server
+			.synthesiser_builder()
+			.with_connection_pool(
+				PoolConfig::default()
+					.with_max_connections(1)
+					.with_idle_timeout(Duration::from_millis(200))
+			)
+			.build()?;
+		testing::synthesise(&synthesiser, &UtteranceConfig::default()).await?;
+		tokio::time::sleep(Duration::from_millis(100)).await;
+
+		// The connection closed by the service is replaced, and the new one is returned to the pool after `turn.end`.
+		testing::synthesise(&synthesiser, &UtteranceConfig::default()).await?;
+		testing::synthesise(&synthesiser, &UtteranceConfig::default()).await?;
+		assert_eq!(server.connections(), 2);
+
+		// Connections idle for longer than the idle timeout are discarded.
+		tokio::time::sleep(Duration::from_millis(250)).await;
+		testing::synthesise(&synthesiser, &UtteranceConfig::default()).await?;
+		assert_eq!(server.connections(), 3);
+		assert!(server.protocol_errors().is_empty());
+		Ok(())
+	}
+}
@@ -0,0 +1,257 @@
+use std::{future::Future, time::Duration};
+
+use futures_util::{Stream, StreamExt};
+
+use super::SynthesisEvent;
+
+/// Policy for retrying synthesis requests which fail with a [retryable](crate::Error::is_retryable) error, such as a
+/// connection failure, throttling, or a transient service error.
+///
+/// By default, a request is only retried if it failed before any audio was received. With
+/// [`RetryPolicy::with_restart_after_audio`], requests which fail mid-stream are also retried from the beginning.
+/// Whenever a retry follows events which were already yielded (e.g. boundaries preceding the first audio chunk), a
+/// [`SynthesisEvent::Restarted`] event signals that all previously received events should be discarded.
+///
+/// [`UtteranceEvent`](speech_synthesis::UtteranceEvent)s have no equivalent of `Restarted`, so requests made through
+/// the [`SpeechSynthesiser`](speech_synthesis::SpeechSynthesiser) trait are never retried once any event has been
+/// yielded; the stream fails with the original error instead.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct RetryPolicy {
+	/// The maximum number of retries per request. `0` disables retries.
+	pub max_retries: u32,
+	/// The delay before the first retry.
+	pub initial_backoff: Duration,
+	/// The maximum delay between retries.
+	pub max_backoff: Duration,
+	/// The factor the delay is multiplied by after each retry.
+	pub multiplier: f32,
+	/// The fraction of the delay, from `0.0` to `1.0`, which is randomised to avoid many clients retrying in lockstep.
+	pub jitter: f32,
+	/// Whether to restart requests which fail after audio has already been received.
+	pub restart_after_audio: bool
+}
+
+impl Default for RetryPolicy {
+	fn default() -> Self {
+		Self {
+			max_retries: 3,
+			initial_backoff: Duration::from_millis(250),
+			max_backoff: Duration::from_secs(8),
+			multiplier: 2.,
+			jitter: 0.5,
+			restart_after_audio: false
+		}
+	}
+}
+
+impl RetryPolicy {
+	/// A policy which never retries.
+	pub fn none() -> Self {
+		Self { max_retries: 0, ..Self::default() }
+	}
+
+	/// Configures the maximum number of retries per request.
+	pub fn with_max_retries(mut self, x: u32) -> Self {
+		self.max_retries = x;
+		self
+	}
+
+	/// Configures the initial & maximum delay between retries.
+	pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+		self.initial_backoff = initial;
+		self.max_backoff = max;
+		self
+	}
+
+	/// Configures the factor the delay is multiplied by after each retry.
+	pub fn with_multiplier(mut self, x: f32) -> Self {
+		self.multiplier = x;
+		self
+	}
+
+	/// Configures the fraction of the delay which is randomised.
+	pub fn with_jitter(mut self, x: f32) -> Self {
+		self.jitter = x.clamp(0., 1.);
+		self
+	}
+
+	/// Configures whether requests which fail after audio has already been received are restarted from the
+	/// beginning. See [`SynthesisEvent::Restarted`].
+	pub fn with_restart_after_audio(mut self, x: bool) -> Self {
+		self.restart_after_audio = x;
+		self
+	}
+
+	/// Returns the delay before the given retry, starting from `0`.
+	pub fn backoff(&self, retry: u32) -> Duration {
+		let backoff = (self.initial_backoff.as_secs_f64() * f64::from(self.multiplier.max(1.)).powi(retry.min(i32::MAX as u32) as i32))
+			.min(self.max_backoff.as_secs_f64());
+		// The fields are public, so `jitter` may be out of range or NaN here even though `with_jitter` clamps it.
+		let jitter = if self.jitter.is_nan() { 0. } else { f64::from(self.jitter).clamp(0., 1.) };
+		Duration::try_from_secs_f64(backoff * (1. - jitter * fastrand::f64())).unwrap_or(self.max_backoff)
+	}
+
+	fn should_retry(&self, error: &crate::Error, retry: u32) -> bool {
+		retry < self.max_retries && error.is_retryable()
+	}
+}
+
+/// Starts a turn with `start`, retrying according to `policy`.
+///
+/// Failures to start the turn are retried before this function returns; failures during the turn are retried by the
+/// returned stream. `start` must release any resources held by a previous attempt's stream before it is called again,
+/// which is guaranteed since the stream is always dropped first.
+///
+/// If `restartable` is `false`, failures after any event has been yielded are never retried, since the consumer has no
+/// way to tell a [`SynthesisEvent::Restarted`] turn apart from the original one.
+pub(crate) async fn retrying<F, Fut, S>(
+	policy: RetryPolicy,
+	restartable: bool,
+	mut start: F
+) -> crate::Result<impl Stream<Item = crate::Result<SynthesisEvent>> + Send + 'static>
+where
+	F: FnMut() -> Fut + Send + 'static,
+	Fut: Future<Output = crate::Result<S>> + Send,
+	S: Stream<Item = crate::Result<SynthesisEvent>> + Send + 'static
+{
+	let mut retry = 0;
+	let first = loop {
+		match start().await {
+			Ok(stream) => break stream,
+			Err(e) if policy.should_retry(&e, retry) => {
+				tracing::debug!("retrying synthesis after error: {e}");
+				tokio::time::sleep(policy.backoff(retry)).await;
+				retry += 1;
+			}
+			Err(e) => return Err(e)
+		}
+	};
+
+	Ok(async_stream_lite::try_async_stream(move |yielder| async move {
+		let mut events = Box::pin(first);
+		let (mut received_audio, mut yielded) = (false, false);
+		loop {
+			let mut error = loop {
+				match events.next().await {
+					None => return Ok(()),
+					Some(Ok(event)) => {
+						received_audio |= matches!(event, SynthesisEvent::AudioChunk(_));
+						yielded = true;
+						yielder.y(event).await;
+					}
+					Some(Err(e)) => break e
+				}
+			};
+			if (yielded && !restartable) || (received_audio && !policy.restart_after_audio) || !policy.should_retry(&error, retry) {
+				return Err(error);
+			}
+
+			// Release the failed attempt's connection before starting another.
+			drop(events);
+			events = loop {
+				tracing::debug!("retrying synthesis after error: {error}");
+				tokio::time::sleep(policy.backoff(retry)).await;
+				retry += 1;
+				match start().await {
+					Ok(next) => break Box::pin(next),
+					Err(e) if policy.should_retry(&e, retry) => error = e,
+					Err(e) => return Err(e)
+				}
+			};
+			if yielded {
+				(received_audio, yielded) = (false, false);
+				yielder.y(SynthesisEvent::Restarted { attempt: retry }).await;
+			}
+		}
+	}))
+}
+
+#[cfg(test)]
+mod tests {
+	use speech_synthesis::{SpeechSynthesiser, UtteranceConfig, UtteranceEvent};
+
+	use super::*;
+	use crate::{
+		AzureOutputFormat,
+		testing::{self, MockServer, MockTurn}
+	};
+
+	#[test]
+	fn test_backoff() {
+		let policy = RetryPolicy::default()
+			.with_backoff(Duration::from_millis(100), Duration::from_secs(1))
+			.with_jitter(0.);
+		assert_eq!(policy.backoff(0), Duration::from_millis(100));
+		assert_eq!(policy.backoff(2), Duration::from_millis(400));
+		assert_eq!(policy.backoff(10), Duration::from_secs(1));
+		assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(1));
+
+		let policy = policy.with_jitter(0.5);
+		for retry in 0..5 {
+			let backoff = policy.backoff(retry);
+			assert!(backoff <= Duration::from_secs(1) && backoff >= Duration::from_millis(50));
+		}
+
+		let mut policy = RetryPolicy::default().with_backoff(Duration::from_secs(1), Duration::MAX);
+		for jitter in [f32::NAN, 2., -1.] {
+			policy.jitter = jitter;
+			assert!(policy.backoff(0) <= Duration::from_secs(1));
+		}
+		assert_eq!(policy.backoff(u32::MAX), Duration::MAX);
+	}
+
+	#[tokio::test]
+	async fn test_retry() -> crate::Result<()> {
+		let server = MockServer::start_with([MockTurn::new().with_turn_start().with_close(1011, "Internal error")]).await?;
+		server.reject_next_connection(503);
+		let retry_policy = RetryPolicy::default().with_backoff(Duration::from_millis(1), Duration::from_millis(1));
+		let synthesiser = server.synthesiser_builder().with_retry_policy(retry_policy).build()?;
+		testing::synthesise(&synthesiser, &UtteranceConfig::default()).await?;
+		assert_eq!(server.connections(), 3);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_restart_after_boundary() -> crate::Result<()> {
+		let server = MockServer::start_with([MockTurn::new()
+			.with_turn_start()
+			.with_word_boundary(50, 300, "Hello")
+			.with_close(1011, "Internal error")])
+		.await?;
+		let retry_policy = RetryPolicy::default().with_backoff(Duration::from_millis(1), Duration::from_millis(1));
+		let synthesiser = server.synthesiser_builder().with_retry_policy(retry_policy).build()?;
+		let config = UtteranceConfig::default().with_emit_word_boundary_events(true);
+		let events = testing::synthesise(&synthesiser, &config).await?;
+		assert!(matches!(&events[0], SynthesisEvent::WordBoundary { text, .. } if &**text == "Hello"));
+		assert!(matches!(&events[1], SynthesisEvent::Restarted { attempt: 1 }));
+		assert!(matches!(&events[2], SynthesisEvent::AudioChunk(_)));
+		assert_eq!(server.connections(), 2);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_no_restart_through_trait() -> crate::Result<()> {
+		let server = MockServer::start_with([MockTurn::new()
+			.with_turn_start()
+			.with_word_boundary(50, 300, "Hello")
+			.with_close(1011, "Internal error")])
+		.await?;
+		let retry_policy = RetryPolicy::default()
+			.with_backoff(Duration::from_millis(1), Duration::from_millis(1))
+			.with_restart_after_audio(true);
+		let synthesiser = server.synthesiser_builder().with_retry_policy(retry_policy).build()?;
+		let format = AzureOutputFormat::Raw24Khz16BitMonoPcm.to_audio_format().unwrap();
+		let config = UtteranceConfig::default().with_emit_word_boundary_events(true);
+		let events: Vec<_> = synthesiser
+			.synthesise_text_stream("Hello, world!", &format, &config)
+			.await?
+			.collect()
+			.await;
+		assert!(matches!(&events[0], Ok(UtteranceEvent::WordBoundary { text, .. }) if &**text == "Hello"));
+		assert!(matches!(&events[1], Err(crate::Error::ServiceUnavailable(_))));
+		assert_eq!(events.len(), 2);
+		assert_eq!(server.connections(), 1);
+		Ok(())
+	}
+}
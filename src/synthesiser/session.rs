@@ -0,0 +1,211 @@
+use std::sync::Arc;
+
+use futures_util::Stream;
+use speech_synthesis::{AudioFormat, AudioFormatPreference, SpeechSynthesiser, UtteranceConfig, UtteranceEventStream};
+use tokio::sync::Mutex;
+
+use super::{
+	AzureCognitiveSpeechServicesSynthesiser, AzureOutputFormat, AzureUtteranceConfig, SsmlTextMap, SynthesisEvent,
+	connection::{self, Connection},
+	text::TextLocator
+};
+use crate::message::{AzureCognitiveSpeechServicesMessage, MetadataOptions};
+
+/// A synthesis session which keeps a single WebSocket connection open across many utterances.
+///
+/// The connection is opened (and sent its `speech.config`) lazily on the first utterance, or eagerly with
+/// [`SynthesisSession::connect`]. Utterances run sequentially on the same connection; starting a new utterance waits
+/// until the stream of the previous one has been driven to completion or dropped. If the service closes the
+/// connection between utterances, a new one is opened transparently.
+///
+/// Cloning a session is cheap, and clones share the same connection.
+#[derive(Clone)]
+pub struct SynthesisSession {
+	synthesiser: AzureCognitiveSpeechServicesSynthesiser,
+	connection: Arc<Mutex<Option<Connection>>>
+}
+
+impl SynthesisSession {
+	pub fn new(synthesiser: AzureCognitiveSpeechServicesSynthesiser) -> Self {
+		Self {
+			synthesiser,
+			connection: Arc::new(Mutex::new(None))
+		}
+	}
+
+	/// Returns the synthesiser this session was created from.
+	pub fn synthesiser(&self) -> &AzureCognitiveSpeechServicesSynthesiser {
+		&self.synthesiser
+	}
+
+	/// Opens the session's connection ahead of time, so the first utterance doesn't have to wait for the handshake.
+	///
+	/// Does nothing if the session already has a usable connection.
+	pub async fn connect(&self) -> crate::Result<()> {
+		let mut slot = self.connection.lock().await;
+		if !slot.as_mut().is_some_and(Connection::is_reusable) {
+			*slot = Some(Connection::open(&self.synthesiser).await?);
+		}
+		Ok(())
+	}
+
+	/// Closes the session's connection, if one is open. The next utterance will open a new connection.
+	pub async fn close(&self) -> crate::Result<()> {
+		if let Some(mut connection) = self.connection.lock().await.take() {
+			connection.channel.close().await?;
+		}
+		Ok(())
+	}
+
+	/// Stream the synthesis of an [`ssml`] document on this session's connection, including Azure-specific events.
+	///
+	/// See [`AzureCognitiveSpeechServicesSynthesiser::synthesise_ssml_events`].
+	pub async fn synthesise_ssml_events(
+		&self,
+		input: &ssml::Speak<'_>,
+		audio_format: &AudioFormat,
+		config: impl Into<AzureUtteranceConfig>
+	) -> crate::Result<impl Stream<Item = crate::Result<SynthesisEvent>> + Send + 'static> {
+		self.ssml_events(input, audio_format, &config.into(), true).await
+	}
+
+	async fn ssml_events(
+		&self,
+		input: &ssml::Speak<'_>,
+		audio_format: &AudioFormat,
+		config: &AzureUtteranceConfig,
+		restartable: bool
+	) -> crate::Result<impl Stream<Item = crate::Result<SynthesisEvent>> + Send + 'static> {
+		let ssml = SsmlTextMap::new(AzureCognitiveSpeechServicesSynthesiser::ssml_for_speak(input, config)?);
+		self.speak_inner(ssml, false, audio_format, config.metadata_options(), restartable).await
+	}
+
+	/// Stream the synthesis of raw text on this session's connection, including Azure-specific events.
+	///
+	/// See [`AzureCognitiveSpeechServicesSynthesiser::synthesise_text_events`].
+	pub async fn synthesise_text_events(
+		&self,
+		input: &str,
+		audio_format: &AudioFormat,
+		config: impl Into<AzureUtteranceConfig>
+	) -> crate::Result<impl Stream<Item = crate::Result<SynthesisEvent>> + Send + 'static> {
+		self.text_events(input, audio_format, &config.into(), true).await
+	}
+
+	async fn text_events(
+		&self,
+		input: &str,
+		audio_format: &AudioFormat,
+		config: &AzureUtteranceConfig,
+		restartable: bool
+	) -> crate::Result<impl Stream<Item = crate::Result<SynthesisEvent>> + Send + 'static> {
+		let ssml = SsmlTextMap::new(AzureCognitiveSpeechServicesSynthesiser::ssml_for_text(input, config)?);
+		self.speak_inner(ssml, true, audio_format, config.metadata_options(), restartable).await
+	}
+
+	async fn speak_inner(
+		&self,
+		ssml: SsmlTextMap,
+		plain_text: bool,
+		audio_format: &AudioFormat,
+		metadata_options: MetadataOptions,
+		restartable: bool
+	) -> crate::Result<impl Stream<Item = crate::Result<SynthesisEvent>> + Send + 'static> {
+		let output_format = AzureCognitiveSpeechServicesSynthesiser::output_format(audio_format)?;
+		let (session, ssml) = (self.clone(), Arc::new(ssml));
+		super::retry::retrying(self.synthesiser.retry_policy.clone(), restartable, move || {
+			let (session, ssml, metadata_options) = (session.clone(), Arc::clone(&ssml), metadata_options.clone());
+			async move { session.start_turn(&ssml, plain_text, output_format, &metadata_options).await }
+		})
+		.await
+	}
+
+	async fn start_turn(
+		&self,
+		ssml: &Arc<SsmlTextMap>,
+		plain_text: bool,
+		output_format: AzureOutputFormat,
+		metadata_options: &MetadataOptions
+	) -> crate::Result<impl Stream<Item = crate::Result<SynthesisEvent>> + Send + 'static> {
+		let slot = Arc::clone(&self.connection).lock_owned().await;
+		let request_id = AzureCognitiveSpeechServicesMessage::gen_request_id();
+		let (connection, turn) = connection::start_turn_in_slot(&self.synthesiser, slot, &request_id, ssml.ssml(), output_format, metadata_options).await?;
+		Ok(super::stream::stream(request_id, connection, turn, self.synthesiser.timeouts, TextLocator::new(Arc::clone(ssml), plain_text)))
+	}
+}
+
+impl SpeechSynthesiser for SynthesisSession {
+	type Error = crate::Error;
+
+	fn negotiate_audio_format(&self, pref: &AudioFormatPreference) -> Option<AudioFormat> {
+		self.synthesiser.negotiate_audio_format(pref)
+	}
+
+	async fn synthesise_ssml_stream(
+		&self,
+		input: &ssml::Speak<'_>,
+		audio_format: &AudioFormat,
+		config: &UtteranceConfig
+	) -> Result<impl UtteranceEventStream<Self::Error> + 'static, Self::Error> {
+		// `UtteranceEvent` has no equivalent of `SynthesisEvent::Restarted`, so never restart once events were yielded.
+		Ok(super::event::utterance_events(self.ssml_events(input, audio_format, &AzureUtteranceConfig::from(config), false).await?))
+	}
+
+	async fn synthesise_text_stream(
+		&self,
+		input: &str,
+		audio_format: &AudioFormat,
+		config: &UtteranceConfig
+	) -> Result<impl UtteranceEventStream<Self::Error> + 'static, Self::Error> {
+		Ok(super::event::utterance_events(self.text_events(input, audio_format, &AzureUtteranceConfig::from(config), false).await?))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use futures_util::TryStreamExt;
+
+	use super::*;
+	use crate::testing::{MockServer, MockTurn};
+
+	#[tokio::test]
+	async fn test_session_reuses_connection() -> crate::Result<()> {
+		let server = MockServer::start().await?;
+		let session = server.synthesiser_builder().build()?.session();
+		let format = AzureOutputFormat::Raw24Khz16BitMonoPcm.to_audio_format().unwrap();
+		for _ in 0..3 {
+			let events: Vec<_> = session
+				.synthesise_text_events("Hello, world!", &format, &UtteranceConfig::default())
+				.await?
+				.try_collect()
+				.await?;
+			assert!(matches!(events.last(), Some(SynthesisEvent::Metrics(_))));
+		}
+		assert_eq!(server.connections(), 1);
+		assert_eq!(server.requests().len(), 3);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_session_reconnects() -> crate::Result<()> {
+		// The service closes the connection after the first turn, while the session sits idle.
+		let server = MockServer::start_with([MockTurn::default().with_delay(Duration::from_millis(20)).with_close(1000, "Idle timeout")]).await?;
+		let session = server.synthesiser_builder().build()?.session();
+		let format = AzureOutputFormat::Raw24Khz16BitMonoPcm.to_audio_format().unwrap();
+		for _ in 0..2 {
+			let events: Vec<_> = session
+				.synthesise_text_events("Hello, world!", &format, &UtteranceConfig::default())
+				.await?
+				.try_collect()
+				.await?;
+			assert!(matches!(events.last(), Some(SynthesisEvent::Metrics(_))));
+			tokio::time::sleep(Duration::from_millis(100)).await;
+		}
+		assert_eq!(server.connections(), 2);
+		assert_eq!(server.requests().len(), 2);
+		assert!(server.protocol_errors().is_empty());
+		Ok(())
+	}
+}
@@ -1,10 +1,20 @@
-use futures_util::{Stream, StreamExt};
-use simd_json::prelude::*;
-use speech_synthesis::{BlendShape, BlendShapeVisemeFrame, UtteranceEvent};
-use tokio::net::TcpStream;
-use tokio_websockets::{MaybeTlsStream, WebSocketStream};
+use std::{ops::DerefMut, time::Duration};
 
-use crate::{Error, message::AzureCognitiveSpeechServicesMessage};
+use futures_util::Stream;
+use speech_synthesis::{BlendShape, BlendShapeVisemeFrame};
+use tokio::time::Instant;
+
+use super::{
+	AzureOutputFormat, SynthesisEvent, SynthesisMetrics, Timeouts,
+	connection::{Connection, Turn},
+	text::TextLocator
+};
+use crate::{
+	Error, ServiceError, ServiceErrorCode,
+	message::{AudioMetadata, AudioMetadataEntry, AzureCognitiveSpeechServicesMessage, AzureCognitiveSpeechServicesMessageError, BoundaryType, Response},
+	recording::Direction,
+	transport::Frame
+};
 
 #[rustfmt::skip]
 const AZURE_BLENDSHAPE_KEYS: [&str; 55] = [
@@ -18,157 +28,317 @@ const AZURE_BLENDSHAPE_KEYS: [&str; 55] = [
 	"cheekSquintRight", "noseSneerLeft", "noseSneerRight", "tongueOut", "headRoll", "leftEyeRoll", "rightEyeRoll"
 ];
 
-pub fn stream(
+pub fn stream<C>(
 	request_id: impl ToString,
-	mut websocket: WebSocketStream<MaybeTlsStream<TcpStream>>
-) -> impl Stream<Item = crate::Result<UtteranceEvent>> + Send + 'static {
+	mut connection: C,
+	turn: Turn,
+	timeouts: Timeouts,
+	text_locator: TextLocator
+) -> impl Stream<Item = crate::Result<SynthesisEvent>> + Send + 'static
+where
+	C: DerefMut<Target = Connection> + Send + 'static
+{
 	let request_id = request_id.to_string();
 
-	async_stream_lite::try_async_stream(|yielder| async move {
-		let mut self_stream_id = None;
-		while let Some(msg) = websocket.next().await {
-			let msg = msg?;
-			let msg: AzureCognitiveSpeechServicesMessage = if msg.is_binary() {
-				(&*msg.into_payload()).try_into()?
-			} else if msg.is_text() {
-				msg.as_text().unwrap().parse()?
-			} else if msg.is_close() {
-				tracing::error!("received unexpected close frame: {:?}", msg.as_close());
+	async_stream_lite::try_async_stream(move |yielder| async move {
+		let started = Instant::from_std(turn.started);
+		let mut parser = TurnParser::new(request_id, Some(turn.output_format), turn.connect_duration).with_text_locator(text_locator);
+		let mut events = Vec::new();
+		loop {
+			// Wait for the next message until whichever of the configured timeouts expires first.
+			let deadline = [
+				timeouts.turn.map(|t| (started + t, Error::TurnTimeout as fn(Duration) -> Error, t)),
+				timeouts
+					.first_audio
+					.filter(|_| parser.awaiting_audio())
+					.map(|t| (started + t, Error::FirstAudioTimeout as _, t)),
+				timeouts.idle.map(|t| (Instant::now() + t, Error::IdleTimeout as _, t))
+			]
+			.into_iter()
+			.flatten()
+			.min_by_key(|(at, ..)| *at);
+			let msg = match deadline {
+				Some((at, error, timeout)) => tokio::time::timeout_at(at, connection.channel.receive())
+					.await
+					.map_err(|_| error(timeout))?,
+				None => connection.channel.receive().await
+			};
+			let Some(msg) = msg else {
 				break;
-			} else {
-				continue;
 			};
 
-			debug_assert_eq!(msg.request_id(), request_id);
-
-			match msg.path() {
-				"turn.start" => continue,
-				"turn.end" => break,
-				"audio" => {
-					yielder
-						.r#yield(UtteranceEvent::AudioChunk(msg.into_body().into_binary().ok_or(Error::ExpectedBinary("audio"))?))
-						.await
+			let msg = match msg? {
+				Frame::Message(msg) => msg,
+				Frame::Close { code, reason } => {
+					if let Some(recorder) = connection.recorder() {
+						recorder.record_close(parser.request_id(), code, &reason);
+					}
+					return Err(parser.close(code, &reason));
 				}
-				"audio.metadata" => {
-					let data = msg.into_json_abstract()?;
-					let metadata = &data
-						.get_array("Metadata")
-						.ok_or(Error::MissingField("Metadata", "`audio.metadata` event"))?[0];
-					let meta_type = metadata.get_str("Type").ok_or(Error::MissingField("Type", "`audio.metadata` event"))?;
-					let metadata = metadata.get("Data").ok_or(Error::MissingField("Data", "`audio.metadata` event"))?;
-
-					let is_boundary = meta_type == "WordBoundary" || meta_type == "SentenceBoundary";
-
-					let (from_millis, to_millis, text) = if is_boundary {
-						// timestamps are given in "ticks", we need to divide by 10,000 to get milliseconds
-						let from_millis = metadata
-							.get_u64("Offset")
-							.map(|o| o as f32 / 10_000.)
-							.ok_or(Error::MissingField("Offset", "`audio.metadata` event"))?;
-						let to_millis = from_millis
-							+ metadata
-								.get_u64("Duration")
-								.map(|o| o as f32 / 10_000.)
-								.ok_or(Error::MissingField("Duration", "`audio.metadata` event"))?;
-						let text = metadata
-							.get("text")
-							.and_then(|v| v.get_str("Text"))
-							.ok_or(Error::MissingField("Text", "`audio.metadata` event"))?
-							.to_owned();
-						(Some(from_millis), Some(to_millis), Some(text))
-					} else {
-						(None, None, None)
-					};
-
-					yielder
-						.r#yield(match meta_type {
-							"Bookmark" => UtteranceEvent::SsmlMark {
-								at_millis: metadata
-									.get_u64("Offset")
-									.map(|o| o as f32 / 10_000.)
-									.ok_or(Error::MissingField("Offset", "`audio.metadata` event"))?,
-								mark: metadata
-									.get_str("Bookmark")
-									.ok_or(Error::MissingField("Bookmark", "`audio.metadata` event"))?
-									.to_string()
-									.into_boxed_str()
-							},
-							"SentenceBoundary" => UtteranceEvent::SentenceBoundary {
-								from_millis: from_millis.unwrap(),
-								to_millis: to_millis.unwrap(),
-								text: text.unwrap().into_boxed_str()
-							},
-							"WordBoundary" => UtteranceEvent::WordBoundary {
-								from_millis: from_millis.unwrap(),
-								to_millis: to_millis.unwrap(),
-								text: text.unwrap().into_boxed_str()
-							},
-							"Viseme" => {
-								// ACSS sends blendshape frames at 60 fps.
-								const FRAME_TICK: f32 = 1000. / 60.;
-
-								#[derive(serde::Deserialize)]
-								struct AnimationChunk {
-									#[serde(rename = "FrameIndex")]
-									frame_index: usize,
-									#[serde(rename = "BlendShapes")]
-									blend_shapes: Vec<Vec<f32>>
-								}
-								let mut chunk = metadata
-									.get_str("AnimationChunk")
-									.ok_or(Error::MissingField("AnimationChunk", "`audio.metadata` event"))?
-									.to_string();
-								let animation_chunk: AnimationChunk = unsafe { simd_json::from_str(&mut chunk) }?;
-
-								let offset_ms = animation_chunk.frame_index as f32 * FRAME_TICK;
-								UtteranceEvent::BlendShapeVisemesChunk(
-									animation_chunk
-										.blend_shapes
-										.into_iter()
-										.enumerate()
-										.map(|(i, keys)| BlendShapeVisemeFrame {
-											frame_offset: offset_ms + (i as f32 * FRAME_TICK),
-											blendshapes: keys
-												.into_iter()
-												.enumerate()
-												.map(|(i, weight)| BlendShape {
-													key: AZURE_BLENDSHAPE_KEYS[i].into(),
-													weight
-												})
-												.collect()
-										})
-										.collect()
-								)
-							}
-							a => unimplemented!("{a}")
-						})
-						.await;
+			};
+			if let Some(recorder) = connection.recorder() {
+				recorder.record(Direction::Received, &msg);
+			}
+
+			let turn_ended = parser.handle(msg, started.elapsed(), &mut events)?;
+			if turn_ended {
+				connection.end_turn();
+			}
+			for event in events.drain(..) {
+				yielder.y(event).await;
+			}
+			if turn_ended {
+				return Ok(());
+			}
+		}
+		Err(parser.disconnected())
+	})
+}
+
+/// Turns the messages the service sends during a turn into [`SynthesisEvent`]s.
+pub(crate) struct TurnParser {
+	output_format: Option<AzureOutputFormat>,
+	metrics: SynthesisMetrics,
+	stream_id: Option<String>,
+	/// The total audio duration reported by the `SessionEnd` metadata, if requested.
+	session_end: Option<Duration>,
+	text_locator: Option<TextLocator>
+}
+
+impl TurnParser {
+	pub fn new(request_id: String, output_format: Option<AzureOutputFormat>, connect_duration: Option<Duration>) -> Self {
+		Self {
+			output_format,
+			metrics: SynthesisMetrics {
+				request_id,
+				connect: connect_duration,
+				turn_start: None,
+				first_audio: None,
+				turn_end: Duration::ZERO,
+				audio_bytes: 0,
+				audio_duration: None
+			},
+			stream_id: None,
+			session_end: None,
+			text_locator: None
+		}
+	}
+
+	/// Locates the text of boundary events in the submitted SSML with `text_locator`.
+	pub fn with_text_locator(mut self, text_locator: TextLocator) -> Self {
+		self.text_locator = Some(text_locator);
+		self
+	}
+
+	pub fn request_id(&self) -> &str {
+		&self.metrics.request_id
+	}
+
+	/// Returns whether no audio has been received yet.
+	pub fn awaiting_audio(&self) -> bool {
+		self.metrics.first_audio.is_none()
+	}
+
+	/// Handles a message received `elapsed` after the turn was started, pushing the resulting events to `events`.
+	/// Returns `true` once the turn has ended.
+	pub fn handle(&mut self, msg: AzureCognitiveSpeechServicesMessage, elapsed: Duration, events: &mut Vec<SynthesisEvent>) -> crate::Result<bool> {
+		debug_assert!(msg.request_id().eq_ignore_ascii_case(&self.metrics.request_id));
+
+		match msg.path() {
+			"turn.start" => {
+				self.metrics.turn_start.get_or_insert(elapsed);
+			}
+			"turn.end" => {
+				self.metrics.turn_end = elapsed;
+				self.metrics.audio_duration = self
+					.session_end
+					.or_else(|| self.output_format.and_then(|format| format.audio_duration(self.metrics.audio_bytes)));
+				events.push(SynthesisEvent::Metrics(self.metrics.clone()));
+				return Ok(true);
+			}
+			"audio" => {
+				let audio = msg.into_body().into_binary().ok_or(Error::ExpectedBinary("audio"))?;
+				self.metrics.first_audio.get_or_insert(elapsed);
+				self.metrics.audio_bytes += audio.len() as u64;
+				events.push(SynthesisEvent::AudioChunk(audio));
+			}
+			"audio.metadata" => {
+				for entry in msg.into_payload::<AudioMetadata>()?.entries {
+					if let AudioMetadataEntry::SessionEnd(data) = &entry {
+						self.session_end = Some(Duration::from_nanos(data.offset.saturating_mul(100)));
+					}
+					events.push(parse_metadata(entry, self.text_locator.as_mut())?);
 				}
-				"response" => {
-					let data = msg.into_json_abstract()?;
-					let audio = data.get("audio").ok_or(Error::MissingField("audio", "`response` event"))?;
-					let audio_type = audio
-						.get_str("type")
-						.ok_or(Error::MissingField("type", "`response` event audio metadata"))?;
-					debug_assert_eq!(audio_type, "inline");
-
-					// we shouldn't be receiving multiple streams in one request
-					let stream_id = audio
-						.get_str("streamId")
-						.ok_or(Error::MissingField("streamId", "`response` event audio metadata"))?;
-					if let Some(self_stream_id) = &self_stream_id {
-						if self_stream_id != stream_id {
-							return Err(Error::UnexpectedMultipleStreams)?;
-						}
-					} else {
-						self_stream_id = Some(stream_id.to_owned());
+			}
+			"response" => {
+				let audio = msg.into_payload::<Response>()?.audio;
+				debug_assert_eq!(audio.kind, "inline");
+
+				// we shouldn't be receiving multiple streams in one request
+				if let Some(self_stream_id) = &self.stream_id {
+					if *self_stream_id != audio.stream_id {
+						Err(Error::UnexpectedMultipleStreams)?;
 					}
+				} else {
+					self.stream_id = Some(audio.stream_id);
+				}
+			}
+			t => {
+				tracing::warn!("ignoring message with unknown path `{t}`");
+			}
+		}
+		Ok(false)
+	}
+
+	/// Returns the error for the service closing the connection mid-turn with the given close frame.
+	pub fn close(&self, code: u16, reason: &str) -> Error {
+		ServiceError::new(ServiceErrorCode::Close(code), reason)
+			.with_request_id(&self.metrics.request_id)
+			.into_error()
+	}
+
+	/// Returns the error for the connection dropping mid-turn without a close frame.
+	pub fn disconnected(&self) -> Error {
+		// The turn always ends with `turn.end` - if we got here, the connection dropped out from under us.
+		self.close(1006, "connection closed before the end of the turn")
+	}
+}
+
+/// Timestamps are given in "ticks" of 100 nanoseconds; we need to divide by 10,000 to get milliseconds.
+fn ticks_to_millis(ticks: u64) -> f32 {
+	ticks as f32 / 10_000.
+}
+
+fn parse_metadata(entry: AudioMetadataEntry, text_locator: Option<&mut TextLocator>) -> crate::Result<SynthesisEvent> {
+	Ok(match entry {
+		AudioMetadataEntry::Bookmark(data) => SynthesisEvent::Bookmark {
+			at_millis: ticks_to_millis(data.offset),
+			mark: data.bookmark.into()
+		},
+		AudioMetadataEntry::WordBoundary(ref data) | AudioMetadataEntry::PunctuationBoundary(ref data) | AudioMetadataEntry::SentenceBoundary(ref data) => {
+			let from_millis = ticks_to_millis(data.offset);
+			let to_millis = from_millis + ticks_to_millis(data.duration);
+			let text = data.text.text.as_str().into();
+			// Punctuation & sentence boundaries may also be reported as a `WordBoundary` with a more specific
+			// `BoundaryType`.
+			let boundary_type = match (data.text.boundary_type, &entry) {
+				(Some(BoundaryType::Punctuation), _) | (None | Some(BoundaryType::Unknown), AudioMetadataEntry::PunctuationBoundary(_)) => {
+					BoundaryType::Punctuation
 				}
-				t => {
-					tracing::error!("unhandled event {t}");
+				(Some(BoundaryType::Sentence), _) | (None | Some(BoundaryType::Unknown), AudioMetadataEntry::SentenceBoundary(_)) => BoundaryType::Sentence,
+				_ => BoundaryType::Word
+			};
+			let text_range = text_locator.and_then(|locator| locator.locate(data, boundary_type));
+			match boundary_type {
+				BoundaryType::Punctuation => SynthesisEvent::PunctuationBoundary {
+					from_millis,
+					to_millis,
+					text,
+					text_range
+				},
+				BoundaryType::Sentence => SynthesisEvent::SentenceBoundary {
+					from_millis,
+					to_millis,
+					text,
+					text_range
+				},
+				_ => SynthesisEvent::WordBoundary {
+					from_millis,
+					to_millis,
+					text,
+					text_range
 				}
 			}
 		}
-		Ok(())
+		// `FacialExpression` animations are JSON blend shape frames, `redlips_front` animations are SVG fragments.
+		AudioMetadataEntry::Viseme(data) => {
+			let at_millis = ticks_to_millis(data.offset);
+			match data.animation_chunk.filter(|chunk| !chunk.trim().is_empty()) {
+				Some(chunk) if chunk.trim_start().starts_with('<') => SynthesisEvent::SvgViseme {
+					at_millis,
+					viseme_id: data.viseme_id,
+					svg: chunk.into_boxed_str()
+				},
+				Some(chunk) => SynthesisEvent::BlendShapeVisemesChunk(parse_blend_shapes(&chunk)?),
+				None => SynthesisEvent::Viseme { at_millis, viseme_id: data.viseme_id }
+			}
+		}
+		AudioMetadataEntry::SessionEnd(data) => SynthesisEvent::SessionEnd {
+			audio_duration_millis: ticks_to_millis(data.offset)
+		},
+		entry => SynthesisEvent::Unknown {
+			kind: entry.kind().into(),
+			raw_json: simd_json::to_string(&entry)
+				.map_err(AzureCognitiveSpeechServicesMessageError::SerializeJson)?
+				.into_boxed_str()
+		}
 	})
 }
+
+fn parse_blend_shapes(chunk: &str) -> crate::Result<Box<[BlendShapeVisemeFrame]>> {
+	// ACSS sends blendshape frames at 60 fps.
+	const FRAME_TICK: f32 = 1000. / 60.;
+
+	#[derive(serde::Deserialize)]
+	struct AnimationChunk {
+		#[serde(rename = "FrameIndex")]
+		frame_index: usize,
+		#[serde(rename = "BlendShapes")]
+		blend_shapes: Vec<Vec<f32>>
+	}
+	let mut chunk = chunk.to_string();
+	let animation_chunk: AnimationChunk = unsafe { simd_json::from_str(&mut chunk) }?;
+
+	let offset_ms = animation_chunk.frame_index as f32 * FRAME_TICK;
+	Ok(animation_chunk
+		.blend_shapes
+		.into_iter()
+		.enumerate()
+		.map(|(i, keys)| BlendShapeVisemeFrame {
+			frame_offset: offset_ms + (i as f32 * FRAME_TICK),
+			blendshapes: keys
+				.into_iter()
+				.zip(AZURE_BLENDSHAPE_KEYS)
+				.map(|(weight, key)| BlendShape { key: key.into(), weight })
+				.collect()
+		})
+		.collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse(json: &str) -> crate::Result<SynthesisEvent> {
+		let mut json = json.to_string();
+		parse_metadata(unsafe { simd_json::from_str(&mut json) }?, None)
+	}
+
+	#[test]
+	fn test_parse_metadata() -> crate::Result<()> {
+		assert!(matches!(
+			parse(r#"{"Type":"SessionEnd","Data":{"Offset":12500000}}"#)?,
+			SynthesisEvent::SessionEnd { audio_duration_millis } if audio_duration_millis == 1250.
+		));
+		assert!(matches!(
+			parse(r#"{"Type":"Viseme","Data":{"Offset":500000,"VisemeId":7}}"#)?,
+			SynthesisEvent::Viseme { at_millis, viseme_id: 7 } if at_millis == 50.
+		));
+		assert!(matches!(
+			parse(r#"{"Type":"Viseme","Data":{"Offset":500000,"VisemeId":21,"AnimationChunk":"<svg><path d=\"M0 0\"/></svg>"}}"#)?,
+			SynthesisEvent::SvgViseme { at_millis, viseme_id: 21, svg } if at_millis == 50. && &*svg == r#"<svg><path d="M0 0"/></svg>"#
+		));
+		assert!(matches!(
+			parse(r#"{"Type":"WordBoundary","Data":{"Offset":0,"Duration":1000000,"text":{"Text":",","Length":1,"BoundaryType":"PunctuationBoundary"}}}"#)?,
+			SynthesisEvent::PunctuationBoundary { to_millis, text, .. } if to_millis == 100. && &*text == ","
+		));
+		match parse(r#"{"Type":"TalkingAvatarSignal","Data":{"Offset":0}}"#)? {
+			SynthesisEvent::Unknown { kind, raw_json } => {
+				assert_eq!(&*kind, "TalkingAvatarSignal");
+				assert!(raw_json.contains(r#""Offset":0"#));
+			}
+			e => panic!("unexpected event {e:?}")
+		}
+		Ok(())
+	}
+}
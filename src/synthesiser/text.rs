@@ -0,0 +1,255 @@
+use std::{ops::Range, sync::Arc};
+
+use crate::message::{BoundaryMetadata, BoundaryType};
+
+/// Maps byte offsets between an SSML document and its text content, i.e. the document with all tags removed and all
+/// entities decoded.
+///
+/// For raw text synthesis, the text content of the generated SSML is exactly the input text, so this maps offsets in
+/// the SSML sent to the service back to offsets in the input.
+#[derive(Debug, Clone)]
+pub struct SsmlTextMap {
+	ssml: Box<str>,
+	text: String,
+	segments: Vec<Segment>
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+	ssml: usize,
+	ssml_len: usize,
+	text: usize,
+	kind: SegmentKind
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SegmentKind {
+	/// Text copied as-is; every byte maps to the same byte in the text content.
+	Literal,
+	/// An entity, which decodes to the given number of bytes of text content.
+	Entity(usize),
+	/// A tag, comment, or processing instruction, which has no text content.
+	Tag
+}
+
+impl Segment {
+	fn text_len(&self) -> usize {
+		match self.kind {
+			SegmentKind::Literal => self.ssml_len,
+			SegmentKind::Entity(len) => len,
+			SegmentKind::Tag => 0
+		}
+	}
+}
+
+impl SsmlTextMap {
+	pub fn new(ssml: impl Into<Box<str>>) -> Self {
+		let ssml = ssml.into();
+		let mut text = String::with_capacity(ssml.len());
+		let mut segments = Vec::new();
+		let mut i = 0;
+		while i < ssml.len() {
+			let rest = &ssml[i..];
+			let text_start = text.len();
+			let (ssml_len, kind) = if rest.starts_with('<') {
+				(rest.find('>').map_or(rest.len(), |end| end + 1), SegmentKind::Tag)
+			} else if let Some((len, decoded)) = rest.starts_with('&').then(|| decode_entity(rest)).flatten() {
+				text.push(decoded);
+				(len, SegmentKind::Entity(decoded.len_utf8()))
+			} else {
+				// The first character may be a stray `&`, which is kept as part of this literal run.
+				let first = rest.chars().next().map_or(1, char::len_utf8);
+				let len = rest[first..].find(['<', '&']).map_or(rest.len(), |end| end + first);
+				text.push_str(&rest[..len]);
+				(len, SegmentKind::Literal)
+			};
+			segments.push(Segment {
+				ssml: i,
+				ssml_len,
+				text: text_start,
+				kind
+			});
+			i += ssml_len;
+		}
+		Self { ssml, text, segments }
+	}
+
+	/// Returns the SSML document.
+	pub fn ssml(&self) -> &str {
+		&self.ssml
+	}
+
+	/// Returns the text content of the SSML document.
+	pub fn text(&self) -> &str {
+		&self.text
+	}
+
+	/// Maps a byte offset into the SSML document to the matching byte offset into its text content, or `None` if the
+	/// offset points inside of a tag or entity.
+	pub fn to_text_offset(&self, ssml_offset: usize) -> Option<usize> {
+		if ssml_offset == self.ssml.len() {
+			return Some(self.text.len());
+		}
+		let segment = self.segments[self.segments.partition_point(|s| s.ssml <= ssml_offset).checked_sub(1)?];
+		match segment.kind {
+			SegmentKind::Literal => Some(segment.text + ssml_offset - segment.ssml),
+			SegmentKind::Entity(_) | SegmentKind::Tag => (ssml_offset == segment.ssml).then_some(segment.text)
+		}
+	}
+
+	/// Maps a byte range of the text content to the range of the SSML document it was decoded from.
+	pub fn to_ssml_range(&self, text_range: Range<usize>) -> Option<Range<usize>> {
+		let last_char = self.text.get(..text_range.end)?.chars().next_back()?;
+		let start = self.to_ssml_offset(text_range.start)?;
+		let last = self.segment_for_text(text_range.end - last_char.len_utf8())?;
+		let end = match last.kind {
+			SegmentKind::Literal => last.ssml + text_range.end - last.text,
+			_ => last.ssml + last.ssml_len
+		};
+		(start < end).then_some(start..end)
+	}
+
+	fn to_ssml_offset(&self, text_offset: usize) -> Option<usize> {
+		let segment = self.segment_for_text(text_offset)?;
+		match segment.kind {
+			SegmentKind::Literal => Some(segment.ssml + text_offset - segment.text),
+			_ => (text_offset == segment.text).then_some(segment.ssml)
+		}
+	}
+
+	/// Returns the segment containing the byte at `text_offset` of the text content.
+	fn segment_for_text(&self, text_offset: usize) -> Option<Segment> {
+		let index = self.segments.partition_point(|s| s.text + s.text_len() <= text_offset);
+		self.segments.get(index).copied()
+	}
+}
+
+fn decode_entity(s: &str) -> Option<(usize, char)> {
+	let end = s.get(..12).unwrap_or(s).find(';')?;
+	let c = match &s[1..end] {
+		"amp" => '&',
+		"lt" => '<',
+		"gt" => '>',
+		"quot" => '"',
+		"apos" => '\'',
+		entity => {
+			let code = entity.strip_prefix('#')?;
+			let code = match code.strip_prefix(['x', 'X']) {
+				Some(hex) => u32::from_str_radix(hex, 16),
+				None => code.parse()
+			};
+			char::from_u32(code.ok()?)?
+		}
+	};
+	Some((end + 1, c))
+}
+
+/// Locates the text of boundary events in the text content of the submitted SSML.
+pub(crate) struct TextLocator {
+	map: Arc<SsmlTextMap>,
+	/// Whether to report ranges into the text content instead of the SSML, i.e. for raw text synthesis.
+	plain_text: bool,
+	/// Where to continue searching for words & punctuation, which are reported in order.
+	word_cursor: usize,
+	/// Where to continue searching for sentences, which are reported ahead of the words they contain.
+	sentence_cursor: usize
+}
+
+impl TextLocator {
+	pub fn new(map: Arc<SsmlTextMap>, plain_text: bool) -> Self {
+		Self {
+			map,
+			plain_text,
+			word_cursor: 0,
+			sentence_cursor: 0
+		}
+	}
+
+	/// Returns the range of the boundary's text in the submitted input, or `None` if it could not be found.
+	pub fn locate(&mut self, boundary: &BoundaryMetadata, boundary_type: BoundaryType) -> Option<Range<usize>> {
+		let needle = boundary.text.text.as_str();
+		if needle.is_empty() {
+			return None;
+		}
+
+		let cursor = match boundary_type {
+			BoundaryType::Sentence => &mut self.sentence_cursor,
+			_ => &mut self.word_cursor
+		};
+		let text = self.map.text();
+		// The service's `TextOffset` counts UTF-16 code units into the SSML; only trust it if the text matches there.
+		let hinted = boundary
+			.text_offset
+			.and_then(|offset| utf16_to_byte_offset(self.map.ssml(), offset as usize))
+			.and_then(|offset| self.map.to_text_offset(offset))
+			.filter(|&start| text.get(start..).is_some_and(|rest| rest.starts_with(needle)));
+		let start = hinted.or_else(|| Some(*cursor + text.get(*cursor..)?.find(needle)?))?;
+		let range = start..start + needle.len();
+		*cursor = range.end;
+
+		if self.plain_text { Some(range) } else { self.map.to_ssml_range(range) }
+	}
+}
+
+fn utf16_to_byte_offset(s: &str, offset: usize) -> Option<usize> {
+	let mut units = 0;
+	for (i, c) in s.char_indices() {
+		if units == offset {
+			return Some(i);
+		}
+		units += c.len_utf16();
+	}
+	(units == offset).then_some(s.len())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::message::BoundaryText;
+
+	#[test]
+	fn test_ssml_text_map() {
+		let map = SsmlTextMap::new(r#"<speak><voice name="v">AT&amp;T &#x263A; café</voice></speak>"#);
+		assert_eq!(map.text(), "AT&T ☺ café");
+
+		let text_start = map.ssml().find("AT").unwrap();
+		assert_eq!(map.to_text_offset(text_start), Some(0));
+		assert_eq!(map.to_text_offset(text_start + 2), Some(2));
+		assert_eq!(map.to_text_offset(text_start + 3), None);
+		assert_eq!(map.to_text_offset(1), None);
+		assert_eq!(map.to_text_offset(map.ssml().find("</voice>").unwrap()), Some(map.text().len()));
+
+		assert_eq!(map.to_ssml_range(0..4).map(|range| &map.ssml()[range]), Some("AT&amp;T"));
+		let smiley = map.text().find('☺').unwrap();
+		assert_eq!(map.to_ssml_range(smiley..smiley + 3).map(|range| &map.ssml()[range]), Some("&#x263A;"));
+		let cafe = map.text().find("café").unwrap();
+		assert_eq!(map.to_ssml_range(cafe..map.text().len()).map(|range| &map.ssml()[range]), Some("café"));
+	}
+
+	#[test]
+	fn test_text_locator() {
+		let boundary = |text: &str, text_offset: Option<u32>| BoundaryMetadata {
+			text: BoundaryText {
+				text: text.to_string(),
+				..BoundaryText::default()
+			},
+			text_offset,
+			..BoundaryMetadata::default()
+		};
+		let map = Arc::new(SsmlTextMap::new(r#"<speak><voice name="hi">hi, hi &amp; bye.</voice></speak>"#));
+
+		let mut locator = TextLocator::new(Arc::clone(&map), true);
+		assert_eq!(locator.locate(&boundary("hi, hi & bye.", None), BoundaryType::Sentence), Some(0..13));
+		assert_eq!(locator.locate(&boundary("hi", None), BoundaryType::Word), Some(0..2));
+		assert_eq!(locator.locate(&boundary(",", None), BoundaryType::Punctuation), Some(2..3));
+		assert_eq!(locator.locate(&boundary("hi", None), BoundaryType::Word), Some(4..6));
+		assert_eq!(locator.locate(&boundary("&", None), BoundaryType::Word), Some(7..8));
+		assert_eq!(locator.locate(&boundary("missing", None), BoundaryType::Word), None);
+		assert_eq!(locator.locate(&boundary("bye", None), BoundaryType::Word), Some(9..12));
+
+		let mut locator = TextLocator::new(Arc::clone(&map), false);
+		assert_eq!(locator.locate(&boundary("&", None), BoundaryType::Word).map(|range| &map.ssml()[range]), Some("&amp;"));
+		let offset = map.ssml().find("bye").unwrap() as u32;
+		assert_eq!(locator.locate(&boundary("bye", Some(offset)), BoundaryType::Word), Some(offset as usize..offset as usize + 3));
+	}
+}
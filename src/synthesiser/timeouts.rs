@@ -0,0 +1,140 @@
+use std::time::Duration;
+
+/// Timeouts for the stages of a synthesis request. A stage configured with `None` may take forever.
+///
+/// Each stage fails with its own [`Error`](crate::Error) variant, so a stalled request can be traced back to the stage
+/// it stalled in.
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct Timeouts {
+	/// The maximum time to open a connection, including obtaining credentials, the WebSocket handshake, and sending
+	/// the `speech.config`. Fails with [`Error::ConnectTimeout`](crate::Error::ConnectTimeout).
+	pub connect: Option<Duration>,
+	/// The maximum time from starting a turn until the first chunk of audio is received. Fails with
+	/// [`Error::FirstAudioTimeout`](crate::Error::FirstAudioTimeout).
+	pub first_audio: Option<Duration>,
+	/// The maximum time to wait between any two messages during a turn. Fails with
+	/// [`Error::IdleTimeout`](crate::Error::IdleTimeout).
+	pub idle: Option<Duration>,
+	/// The maximum duration of a turn, from starting it until `turn.end` is received. Fails with
+	/// [`Error::TurnTimeout`](crate::Error::TurnTimeout).
+	pub turn: Option<Duration>
+}
+
+impl Default for Timeouts {
+	fn default() -> Self {
+		Self {
+			connect: Some(Duration::from_secs(10)),
+			first_audio: Some(Duration::from_secs(20)),
+			idle: Some(Duration::from_secs(20)),
+			turn: None
+		}
+	}
+}
+
+impl Timeouts {
+	/// Timeouts which never expire.
+	pub fn none() -> Self {
+		Self {
+			connect: None,
+			first_audio: None,
+			idle: None,
+			turn: None
+		}
+	}
+
+	/// Configures the maximum time to open a connection.
+	pub fn with_connect(mut self, x: impl Into<Option<Duration>>) -> Self {
+		self.connect = x.into();
+		self
+	}
+
+	/// Configures the maximum time until the first chunk of audio is received.
+	pub fn with_first_audio(mut self, x: impl Into<Option<Duration>>) -> Self {
+		self.first_audio = x.into();
+		self
+	}
+
+	/// Configures the maximum time to wait between messages during a turn.
+	pub fn with_idle(mut self, x: impl Into<Option<Duration>>) -> Self {
+		self.idle = x.into();
+		self
+	}
+
+	/// Configures the maximum duration of a turn.
+	pub fn with_turn(mut self, x: impl Into<Option<Duration>>) -> Self {
+		self.turn = x.into();
+		self
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use speech_synthesis::UtteranceConfig;
+
+	use super::*;
+	use crate::{
+		Error,
+		testing::{self, MockServer, MockTurn}
+	};
+
+	#[tokio::test]
+	async fn test_idle_timeout() -> crate::Result<()> {
+		let server = MockServer::start_with([MockTurn::new().with_turn_start().with_delay(Duration::from_secs(1)).with_turn_end()]).await?;
+		let synthesiser = server
+			.synthesiser_builder()
+			.with_timeouts(Timeouts::none().with_idle(Duration::from_millis(50)))
+			.build()?;
+		assert!(matches!(testing::synthesise(&synthesiser, &UtteranceConfig::default()).await, Err(Error::IdleTimeout(_))));
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_connect_timeout() -> crate::Result<()> {
+		let server = MockServer::start().await?;
+		server.delay_next_connection(Duration::from_secs(1));
+		let synthesiser = server
+			.synthesiser_builder()
+			.with_timeouts(Timeouts::none().with_connect(Duration::from_millis(50)))
+			.build()?;
+		assert!(matches!(testing::synthesise(&synthesiser, &UtteranceConfig::default()).await, Err(Error::ConnectTimeout(_))));
+		assert!(server.requests().is_empty());
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_first_audio_timeout() -> crate::Result<()> {
+		// Messages keep arriving, but none of them carry audio.
+		let mut turn = MockTurn::new().with_turn_start().with_response();
+		for i in 0..20 {
+			turn = turn.with_delay(Duration::from_millis(20)).with_bookmark(i, "mark");
+		}
+		let server = MockServer::start_with([turn.with_audio(vec![0; 4800]).with_turn_end()]).await?;
+		let synthesiser = server
+			.synthesiser_builder()
+			.with_timeouts(
+				Timeouts::none()
+					.with_first_audio(Duration::from_millis(100))
+					.with_idle(Duration::from_secs(1))
+			)
+			.build()?;
+		assert!(matches!(testing::synthesise(&synthesiser, &UtteranceConfig::default()).await, Err(Error::FirstAudioTimeout(_))));
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_turn_timeout() -> crate::Result<()> {
+		let mut turn = MockTurn::new().with_turn_start().with_response().with_audio(vec![0; 4800]);
+		for i in 0..20 {
+			turn = turn.with_delay(Duration::from_millis(20)).with_word_boundary(i * 100, 100, "Hello");
+		}
+		let server = MockServer::start_with([turn.with_turn_end()]).await?;
+		let synthesiser = server
+			.synthesiser_builder()
+			.with_timeouts(Timeouts::none().with_turn(Duration::from_millis(100)).with_idle(Duration::from_secs(1)))
+			.build()?;
+		let config = UtteranceConfig::default().with_emit_word_boundary_events(true);
+		assert!(matches!(testing::synthesise(&synthesiser, &config).await, Err(Error::TurnTimeout(_))));
+		Ok(())
+	}
+}
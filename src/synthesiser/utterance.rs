@@ -0,0 +1,204 @@
+use speech_synthesis::UtteranceConfig;
+
+use super::VisemeType;
+use crate::message::MetadataOptions;
+
+/// Configuration for a single utterance, including Azure-specific options which have no [`UtteranceConfig`]
+/// equivalent.
+///
+/// Each option enables one type of `audio.metadata` the service sends, and the matching [`SynthesisEvent`]. Converting
+/// from an [`UtteranceConfig`] enables the events it asks for, plus bookmarks.
+///
+/// [`SynthesisEvent`]: super::SynthesisEvent
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct AzureUtteranceConfig {
+	/// The voice & language used for raw text synthesis. Its `emit_*` options are superseded by the options below.
+	pub utterance: UtteranceConfig,
+	/// Whether to emit [`SynthesisEvent::WordBoundary`](super::SynthesisEvent::WordBoundary) events.
+	pub word_boundaries: bool,
+	/// Whether to emit [`SynthesisEvent::SentenceBoundary`](super::SynthesisEvent::SentenceBoundary) events.
+	pub sentence_boundaries: bool,
+	/// Whether to emit [`SynthesisEvent::PunctuationBoundary`](super::SynthesisEvent::PunctuationBoundary) events.
+	pub punctuation_boundaries: bool,
+	/// Whether to emit [`SynthesisEvent::Bookmark`](super::SynthesisEvent::Bookmark) events.
+	pub bookmarks: bool,
+	/// Whether to emit [`SynthesisEvent::Viseme`](super::SynthesisEvent::Viseme) events, or the animated viseme
+	/// events matching [`viseme_type`](Self::viseme_type).
+	pub visemes: bool,
+	/// The type of viseme animation to request, if [`visemes`](Self::visemes) are enabled. Animations are requested in
+	/// each `<voice>` of the SSML, so SSML without one is wrapped in the configured voice.
+	pub viseme_type: VisemeType,
+	/// Whether to emit a [`SynthesisEvent::SessionEnd`](super::SynthesisEvent::SessionEnd) event carrying the total
+	/// audio duration.
+	pub session_end: bool
+}
+
+impl Default for AzureUtteranceConfig {
+	fn default() -> Self {
+		Self::from(&UtteranceConfig::default())
+	}
+}
+
+impl AzureUtteranceConfig {
+	/// Configures the voice & language used for raw text synthesis.
+	pub fn with_utterance(mut self, utterance: UtteranceConfig) -> Self {
+		self.utterance = utterance;
+		self
+	}
+
+	/// Configures whether to emit [`SynthesisEvent::WordBoundary`](super::SynthesisEvent::WordBoundary) events.
+	pub fn with_word_boundaries(mut self, x: bool) -> Self {
+		self.word_boundaries = x;
+		self
+	}
+
+	/// Configures whether to emit [`SynthesisEvent::SentenceBoundary`](super::SynthesisEvent::SentenceBoundary) events.
+	pub fn with_sentence_boundaries(mut self, x: bool) -> Self {
+		self.sentence_boundaries = x;
+		self
+	}
+
+	/// Configures whether to emit [`SynthesisEvent::PunctuationBoundary`](super::SynthesisEvent::PunctuationBoundary)
+	/// events.
+	pub fn with_punctuation_boundaries(mut self, x: bool) -> Self {
+		self.punctuation_boundaries = x;
+		self
+	}
+
+	/// Configures whether to emit [`SynthesisEvent::Bookmark`](super::SynthesisEvent::Bookmark) events.
+	pub fn with_bookmarks(mut self, x: bool) -> Self {
+		self.bookmarks = x;
+		self
+	}
+
+	/// Configures whether to emit viseme events.
+	pub fn with_visemes(mut self, x: bool) -> Self {
+		self.visemes = x;
+		self
+	}
+
+	/// Enables viseme events, requesting animations of the given type.
+	pub fn with_viseme_type(mut self, viseme_type: VisemeType) -> Self {
+		self.visemes = true;
+		self.viseme_type = viseme_type;
+		self
+	}
+
+	/// Configures whether to emit a [`SynthesisEvent::SessionEnd`](super::SynthesisEvent::SessionEnd) event.
+	pub fn with_session_end(mut self, x: bool) -> Self {
+		self.session_end = x;
+		self
+	}
+
+	pub(crate) fn metadata_options(&self) -> MetadataOptions {
+		MetadataOptions {
+			bookmark_enabled: self.bookmarks,
+			punctuation_boundary_enabled: self.punctuation_boundaries,
+			sentence_boundary_enabled: self.sentence_boundaries,
+			session_end_enabled: self.session_end,
+			viseme_enabled: self.visemes,
+			word_boundary_enabled: self.word_boundaries
+		}
+	}
+}
+
+/// `emit_visemes` requests [`VisemeType::FacialExpression`] animations, since blend shapes are the only visemes
+/// [`UtteranceEvent`](speech_synthesis::UtteranceEvent) can represent; viseme IDs alone would never reach
+/// [`SpeechSynthesiser`](speech_synthesis::SpeechSynthesiser) users.
+impl From<&UtteranceConfig> for AzureUtteranceConfig {
+	fn from(config: &UtteranceConfig) -> Self {
+		Self {
+			word_boundaries: config.emit_word_boundary_events,
+			sentence_boundaries: config.emit_sentence_boundary_events,
+			punctuation_boundaries: false,
+			bookmarks: true,
+			visemes: config.emit_visemes,
+			viseme_type: if config.emit_visemes { VisemeType::FacialExpression } else { VisemeType::Id },
+			session_end: false,
+			utterance: config.clone()
+		}
+	}
+}
+
+impl From<UtteranceConfig> for AzureUtteranceConfig {
+	fn from(config: UtteranceConfig) -> Self {
+		Self::from(&config)
+	}
+}
+
+impl From<&AzureUtteranceConfig> for AzureUtteranceConfig {
+	fn from(config: &AzureUtteranceConfig) -> Self {
+		config.clone()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use futures_util::TryStreamExt;
+	use speech_synthesis::{SpeechSynthesiser, UtteranceEvent};
+
+	use super::*;
+	use crate::{
+		AzureOutputFormat, SynthesisEvent,
+		testing::{self, MockServer, MockTurn}
+	};
+
+	#[tokio::test]
+	async fn test_metadata_options() -> crate::Result<()> {
+		let server = MockServer::start_with([MockTurn::new()
+			.with_turn_start()
+			.with_response()
+			.with_audio(vec![0; 4800])
+			.with_metadata(r#"{"Type":"SessionEnd","Data":{"Offset":1250000}}"#)
+			.with_turn_end()])
+		.await?;
+		let synthesiser = server.synthesiser_builder().build()?;
+		let config = AzureUtteranceConfig::from(UtteranceConfig::default().with_voice("en-US-JennyNeural").with_emit_visemes(true))
+			.with_punctuation_boundaries(true)
+			.with_bookmarks(false)
+			.with_session_end(true);
+		let events = testing::synthesise(&synthesiser, &config).await?;
+		assert!(matches!(&events[1], SynthesisEvent::SessionEnd { audio_duration_millis } if *audio_duration_millis == 125.));
+		assert!(matches!(&events[2], SynthesisEvent::Metrics(metrics) if metrics.audio_duration == Some(Duration::from_millis(125))));
+
+		let metadata_options = &server.requests()[0].context.synthesis.audio.metadata_options;
+		assert!(metadata_options.punctuation_boundary_enabled && metadata_options.viseme_enabled && metadata_options.session_end_enabled);
+		assert!(!metadata_options.bookmark_enabled && !metadata_options.word_boundary_enabled && !metadata_options.sentence_boundary_enabled);
+
+		// The `SpeechSynthesiser` defaults still request bookmarks.
+		testing::synthesise(&synthesiser, &UtteranceConfig::default()).await?;
+		assert_eq!(
+			server.requests()[1].context.synthesis.audio.metadata_options,
+			MetadataOptions {
+				bookmark_enabled: true,
+				..MetadataOptions::default()
+			}
+		);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_utterance_config_visemes() -> crate::Result<()> {
+		let server = MockServer::start_with([MockTurn::new()
+			.with_turn_start()
+			.with_response()
+			.with_metadata(r#"{"Type":"Viseme","Data":{"Offset":0,"VisemeId":0,"AnimationChunk":"{\"FrameIndex\":0,\"BlendShapes\":[[0.5,0.25]]}"}}"#)
+			.with_audio(vec![0; 4800])
+			.with_turn_end()])
+		.await?;
+		let format = AzureOutputFormat::Raw24Khz16BitMonoPcm.to_audio_format().unwrap();
+		let events: Vec<_> = server
+			.synthesiser_builder()
+			.build()?
+			.synthesise_text_stream("Hello, world!", &format, &UtteranceConfig::default().with_voice("en-US-JennyNeural").with_emit_visemes(true))
+			.await?
+			.try_collect()
+			.await?;
+		assert!(server.requests()[0].ssml.contains(r#"<mstts:viseme type="FacialExpression"/>"#));
+		assert!(matches!(&events[0], UtteranceEvent::BlendShapeVisemesChunk(frames) if frames[0].blendshapes[1].weight == 0.25));
+		Ok(())
+	}
+}
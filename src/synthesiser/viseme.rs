@@ -0,0 +1,301 @@
+use std::fmt;
+
+use ssml::visit_mut::VisitMut;
+
+use super::SynthesisEvent;
+use crate::Error;
+
+/// The type of viseme animation to request alongside viseme IDs.
+///
+/// The service always sends [`SynthesisEvent::Viseme`](super::SynthesisEvent::Viseme) events with the ID of each
+/// viseme when [visemes](super::AzureUtteranceConfig::visemes) are enabled. Other types additionally attach an
+/// animation to each event, and are requested with an `<mstts:viseme>` element in each `<voice>` of the SSML.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum VisemeType {
+	/// Only viseme IDs; see [`viseme_phonemes`] for what each ID represents.
+	#[default]
+	Id,
+	/// `redlips_front`: 2D SVG animations of a front-facing pair of lips, emitted as
+	/// [`SynthesisEvent::SvgViseme`](super::SynthesisEvent::SvgViseme).
+	RedlipsFront,
+	/// `FacialExpression`: 3D blend shape animations, emitted as
+	/// [`SynthesisEvent::BlendShapeVisemesChunk`](super::SynthesisEvent::BlendShapeVisemesChunk).
+	FacialExpression
+}
+
+impl VisemeType {
+	/// Returns the `type` of the `<mstts:viseme>` element requesting this type, or `None` if no element is needed.
+	pub fn as_ssml_type(&self) -> Option<&'static str> {
+		match self {
+			Self::Id => None,
+			Self::RedlipsFront => Some("redlips_front"),
+			Self::FacialExpression => Some("FacialExpression")
+		}
+	}
+}
+
+impl fmt::Display for VisemeType {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.as_ssml_type().unwrap_or("id"))
+	}
+}
+
+/// Inserts an `<mstts:viseme>` element requesting `viseme_type` at the start of every `<voice>` in `speak`.
+///
+/// If `speak` has no `<voice>`, its content is wrapped in `default_voice` to hold the element. Fails with
+/// [`Error::VisemesWithoutVoice`] if there is no default voice either.
+pub(crate) fn request_viseme_type(speak: &mut ssml::Speak<'_>, viseme_type: VisemeType, default_voice: Option<&str>) -> crate::Result<()> {
+	struct InsertVisemeElement {
+		kind: &'static str,
+		inserted: bool
+	}
+
+	// `ssml::visit_mut` dispatches elements straight to its free functions rather than the trait's `visit_voice_mut`,
+	// so voices have to be picked out of `visit_element_mut`.
+	impl<'s> VisitMut<'s> for InsertVisemeElement {
+		fn visit_element_mut(&mut self, node: &'s mut ssml::Element) {
+			if let ssml::Element::Voice(voice) = node {
+				voice
+					.children_mut()
+					.insert(0, ssml::CustomElement::new("mstts:viseme").with_attr("type", self.kind).into());
+				self.inserted = true;
+			}
+			ssml::visit_mut::visit_element_mut(self, node);
+		}
+	}
+
+	let Some(kind) = viseme_type.as_ssml_type() else {
+		return Ok(());
+	};
+	let mut visitor = InsertVisemeElement { kind, inserted: false };
+	ssml::visit_mut::visit_speak_mut(&mut visitor, speak);
+	if !visitor.inserted {
+		let voice = default_voice.ok_or(Error::VisemesWithoutVoice(viseme_type))?;
+		let children = std::mem::take(speak.children_mut());
+		speak.push(ssml::voice(voice.to_string(), children));
+		ssml::visit_mut::visit_speak_mut(&mut visitor, speak);
+	}
+	Ok(())
+}
+
+/// The phonemes represented by each Azure viseme ID, as [IPA](https://en.wikipedia.org/wiki/International_Phonetic_Alphabet)
+/// symbols for `en-US` voices. ID `0` is silence.
+///
+/// | ID | Phonemes          | ID | Phonemes       |
+/// |----|-------------------|----|----------------|
+/// | 0  | *silence*         | 11 | aɪ             |
+/// | 1  | æ, ə, ʌ           | 12 | h              |
+/// | 2  | ɑ                 | 13 | ɹ              |
+/// | 3  | ɔ                 | 14 | l              |
+/// | 4  | ɛ, ʊ              | 15 | s, z           |
+/// | 5  | ɝ                 | 16 | ʃ, tʃ, dʒ, ʒ   |
+/// | 6  | j, i, ɪ           | 17 | ð              |
+/// | 7  | w, u              | 18 | f, v           |
+/// | 8  | o                 | 19 | d, t, n, θ     |
+/// | 9  | aʊ                | 20 | k, g, ŋ        |
+/// | 10 | ɔɪ                | 21 | p, b, m        |
+///
+/// See the [Azure documentation](https://learn.microsoft.com/azure/ai-services/speech-service/how-to-speech-synthesis-viseme#map-phonemes-to-visemes)
+/// for other languages.
+pub const VISEME_PHONEMES: [&[&str]; 22] = [
+	&[],
+	&["æ", "ə", "ʌ"],
+	&["ɑ"],
+	&["ɔ"],
+	&["ɛ", "ʊ"],
+	&["ɝ"],
+	&["j", "i", "ɪ"],
+	&["w", "u"],
+	&["o"],
+	&["aʊ"],
+	&["ɔɪ"],
+	&["aɪ"],
+	&["h"],
+	&["ɹ"],
+	&["l"],
+	&["s", "z"],
+	&["ʃ", "tʃ", "dʒ", "ʒ"],
+	&["ð"],
+	&["f", "v"],
+	&["d", "t", "n", "θ"],
+	&["k", "g", "ŋ"],
+	&["p", "b", "m"]
+];
+
+/// Returns the phonemes represented by an Azure viseme ID, or `None` if the ID is unknown. See [`VISEME_PHONEMES`].
+pub fn viseme_phonemes(viseme_id: u8) -> Option<&'static [&'static str]> {
+	VISEME_PHONEMES.get(viseme_id as usize).copied()
+}
+
+/// Assembles the SVG fragments of [`SynthesisEvent::SvgViseme`] events into a single SVG document, which uses SMIL
+/// `<set>` animations to show each fragment from its offset until the next one.
+///
+/// The document's root attributes (e.g. its `viewBox`) are taken from the first fragment which has an `<svg>` root.
+/// Animations start when the document is loaded; to synchronise with audio playback, load or restart the document
+/// (e.g. with `SVGSVGElement.setCurrentTime(0)`) as playback begins.
+///
+/// ```
+/// # use azure_cognitive_speech_services::{SvgVisemeAnimation, SynthesisEvent};
+/// # fn assemble(events: Vec<SynthesisEvent>) -> String {
+/// let mut animation = SvgVisemeAnimation::new();
+/// animation.extend(&events);
+/// animation.to_svg(None)
+/// # }
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct SvgVisemeAnimation {
+	root_attrs: Option<String>,
+	frames: Vec<(f32, String)>
+}
+
+impl SvgVisemeAnimation {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Adds the fragment `svg`, shown from `at_millis`. Fragments may be added in any order.
+	pub fn push(&mut self, at_millis: f32, svg: &str) {
+		let (root_attrs, content) = split_svg_root(svg);
+		if self.root_attrs.is_none() {
+			self.root_attrs = root_attrs.map(str::to_string);
+		}
+		self.frames.push((at_millis, content.to_string()));
+	}
+
+	/// Adds the fragment of an [`SynthesisEvent::SvgViseme`] event, returning `false` if `event` is any other event.
+	pub fn push_event(&mut self, event: &SynthesisEvent) -> bool {
+		match event {
+			SynthesisEvent::SvgViseme { at_millis, svg, .. } => {
+				self.push(*at_millis, svg);
+				true
+			}
+			_ => false
+		}
+	}
+
+	/// Returns the number of fragments added.
+	pub fn len(&self) -> usize {
+		self.frames.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.frames.is_empty()
+	}
+
+	/// Renders the animation as a standalone SVG document. The last fragment is shown until `duration_millis`, e.g.
+	/// the audio duration from [`SynthesisEvent::SessionEnd`], or indefinitely if `None`.
+	pub fn to_svg(&self, duration_millis: Option<f32>) -> String {
+		let mut frames: Vec<_> = self.frames.iter().collect();
+		frames.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+		let root_attrs = self.root_attrs.as_deref().unwrap_or_default();
+		let mut svg = String::from("<svg");
+		if !root_attrs.contains("xmlns=") {
+			svg.push_str(r#" xmlns="http://www.w3.org/2000/svg""#);
+		}
+		if !root_attrs.is_empty() {
+			svg.push(' ');
+			svg.push_str(root_attrs);
+		}
+		svg.push('>');
+		for (i, (at_millis, content)) in frames.iter().enumerate() {
+			let end_millis = frames.get(i + 1).map(|(at_millis, _)| *at_millis).or(duration_millis);
+			svg.push_str(r#"<g visibility="hidden">"#);
+			svg.push_str(content);
+			svg.push_str(&format!(r#"<set attributeName="visibility" to="visible" begin="{:.3}s""#, at_millis / 1000.));
+			if let Some(end_millis) = end_millis {
+				svg.push_str(&format!(r#" end="{:.3}s""#, end_millis / 1000.));
+			}
+			svg.push_str("/></g>");
+		}
+		svg.push_str("</svg>");
+		svg
+	}
+}
+
+impl<'e> Extend<&'e SynthesisEvent> for SvgVisemeAnimation {
+	/// Adds the fragments of all [`SynthesisEvent::SvgViseme`] events, ignoring any other events.
+	fn extend<I: IntoIterator<Item = &'e SynthesisEvent>>(&mut self, events: I) {
+		for event in events {
+			self.push_event(event);
+		}
+	}
+}
+
+/// Splits an SVG fragment into the attributes of its `<svg>` root element & its content. Fragments without an `<svg>`
+/// root are returned as-is.
+fn split_svg_root(svg: &str) -> (Option<&str>, &str) {
+	let Some(start) = svg.find("<svg") else {
+		return (None, svg.trim());
+	};
+	let Some(tag_len) = svg[start..].find('>') else {
+		return (None, svg.trim());
+	};
+	let tag = &svg[start + "<svg".len()..start + tag_len];
+	if let Some(attrs) = tag.strip_suffix('/') {
+		return (Some(attrs.trim()), "");
+	}
+	let content = &svg[start + tag_len + 1..];
+	let content = content.rfind("</svg>").map_or(content, |end| &content[..end]);
+	(Some(tag.trim()), content.trim())
+}
+
+#[cfg(test)]
+mod tests {
+	use ssml::Serialize;
+
+	use super::*;
+
+	#[test]
+	fn test_request_viseme_type() -> crate::Result<()> {
+		let serialize = |mut speak: ssml::Speak<'_>, viseme_type, default_voice| -> crate::Result<String> {
+			request_viseme_type(&mut speak, viseme_type, default_voice)?;
+			Ok(speak.serialize_to_string(&ssml::SerializeOptions::default().flavor(ssml::Flavor::MicrosoftAzureCognitiveSpeechServices))?)
+		};
+		let with_voice = || ssml::speak(Some("en-US"), [ssml::voice("en-US-JennyNeural", ["Hello"])]);
+		assert!(!serialize(with_voice(), VisemeType::Id, None)?.contains("mstts:viseme"));
+		assert!(
+			serialize(with_voice(), VisemeType::FacialExpression, None)?
+				.contains(r#"<voice name="en-US-JennyNeural"><mstts:viseme type="FacialExpression"/>Hello"#)
+		);
+		assert!(serialize(with_voice(), VisemeType::RedlipsFront, Some("en-US-AvaNeural"))?.contains(r#"<mstts:viseme type="redlips_front"/>"#));
+
+		// Without a `<voice>`, the content is wrapped in the default voice, if there is one.
+		let without_voice = || ssml::speak(Some("en-US"), ["Hello"]);
+		assert!(
+			serialize(without_voice(), VisemeType::FacialExpression, Some("en-US-AvaNeural"))?
+				.contains(r#"<voice name="en-US-AvaNeural"><mstts:viseme type="FacialExpression"/>Hello</voice>"#)
+		);
+		assert!(matches!(serialize(without_voice(), VisemeType::FacialExpression, None), Err(Error::VisemesWithoutVoice(VisemeType::FacialExpression))));
+		assert!(!serialize(without_voice(), VisemeType::Id, None)?.contains("mstts:viseme"));
+
+		assert_eq!(viseme_phonemes(0), Some(&[][..]));
+		assert_eq!(viseme_phonemes(21), Some(&["p", "b", "m"][..]));
+		assert_eq!(viseme_phonemes(22), None);
+		Ok(())
+	}
+
+	#[test]
+	fn test_svg_viseme_animation() {
+		let mut animation = SvgVisemeAnimation::new();
+		assert!(animation.push_event(&SynthesisEvent::SvgViseme {
+			at_millis: 100.,
+			viseme_id: 21,
+			svg: r#"<?xml version="1.0"?><svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 100 100"><path d="M1 1"/></svg>"#.into()
+		}));
+		animation.push(0., r#"<path d="M0 0"/>"#);
+		assert!(!animation.push_event(&SynthesisEvent::Bookmark { at_millis: 0., mark: "mark".into() }));
+		assert_eq!(animation.len(), 2);
+		assert_eq!(
+			animation.to_svg(Some(250.)),
+			concat!(
+				r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 100 100">"#,
+				r#"<g visibility="hidden"><path d="M0 0"/><set attributeName="visibility" to="visible" begin="0.000s" end="0.100s"/></g>"#,
+				r#"<g visibility="hidden"><path d="M1 1"/><set attributeName="visibility" to="visible" begin="0.100s" end="0.250s"/></g>"#,
+				"</svg>"
+			)
+		);
+		assert!(!animation.to_svg(None).contains(r#"end="0.250s""#));
+	}
+}
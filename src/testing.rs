@@ -0,0 +1,477 @@
+//! An in-process mock of the Azure text-to-speech WebSocket service, for testing without network access.
+//!
+//! The [`MockServer`] listens on a local port and speaks the same protocol as the real service: it checks that each
+//! connection sends a `speech.config`, and that each turn consists of a `synthesis.context` followed by an `ssml`
+//! message with the same request ID. Each turn is then answered with the next scripted [`MockTurn`].
+//!
+//! ```no_run
+//! # async fn f() -> Result<(), Box<dyn std::error::Error>> {
+//! use azure_cognitive_speech_services::testing::{MockServer, MockTurn};
+//!
+//! let server = MockServer::start().await?;
+//! server.push_turn(MockTurn::success(vec![0; 3200]));
+//! server.push_turn(MockTurn::new().with_turn_start().with_close(1007, "Invalid SSML"));
+//! let synthesiser = server.synthesiser_builder().build()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+	collections::VecDeque,
+	net::SocketAddr,
+	sync::{
+		Arc, Mutex,
+		atomic::{AtomicUsize, Ordering}
+	},
+	time::Duration
+};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt},
+	net::{TcpListener, TcpStream},
+	task::{JoinHandle, JoinSet}
+};
+use tokio_websockets::{CloseCode, Message, ServerBuilder, WebSocketStream};
+
+use crate::{
+	AzureCognitiveSpeechServicesSynthesiserBuilder,
+	message::{
+		AudioMetadata, AudioMetadataEntry, AzureCognitiveSpeechServicesMessage, BookmarkMetadata, BoundaryMetadata, BoundaryText, BoundaryType, MessagePayload,
+		Response, ResponseAudio, ServiceContext, SpeechConfig, SynthesisContext, TurnEnd, TurnStart
+	}
+};
+
+const STREAM_ID: &str = "mockstream";
+
+#[derive(Debug, Clone)]
+enum Step {
+	Text { path: &'static str, body: String },
+	Audio(Vec<u8>),
+	Close(u16, String),
+	Delay(Duration)
+}
+
+/// How the [`MockServer`] handles the WebSocket upgrade request of a connection.
+#[derive(Debug, Clone, Copy)]
+enum Handshake {
+	Reject(u16),
+	Delay(Duration)
+}
+
+/// A scripted response of the [`MockServer`] to a single turn.
+///
+/// Steps are sent in the order they were added. The [`Default`] turn is a successful synthesis with a short chunk of
+/// silence.
+#[derive(Debug, Clone)]
+pub struct MockTurn {
+	steps: Vec<Step>
+}
+
+impl Default for MockTurn {
+	fn default() -> Self {
+		Self::success(vec![0; 3200])
+	}
+}
+
+impl MockTurn {
+	/// Creates an empty turn, which sends nothing.
+	pub fn new() -> Self {
+		Self { steps: Vec::new() }
+	}
+
+	/// Creates a successful turn which sends `audio` in a single chunk.
+	pub fn success(audio: impl Into<Vec<u8>>) -> Self {
+		Self::new().with_turn_start().with_response().with_audio(audio).with_turn_end()
+	}
+
+	/// Sends a `turn.start` message.
+	pub fn with_turn_start(self) -> Self {
+		self.with_payload(&TurnStart { context: Some(service_context()) })
+	}
+
+	/// Sends a `response` message announcing the audio stream.
+	pub fn with_response(self) -> Self {
+		self.with_payload(&Response {
+			context: Some(service_context()),
+			audio: ResponseAudio {
+				kind: "inline".to_string(),
+				stream_id: STREAM_ID.to_string()
+			}
+		})
+	}
+
+	/// Sends an `audio.metadata` message containing a single raw metadata entry, e.g.
+	/// `{"Type":"SessionEnd","Data":{"Offset":12500000}}`.
+	pub fn with_metadata(self, entry: impl AsRef<str>) -> Self {
+		self.with_message("audio.metadata", format!(r#"{{"Metadata":[{}]}}"#, entry.as_ref()))
+	}
+
+	/// Sends an `audio.metadata` message containing a single typed metadata entry.
+	pub fn with_metadata_entry(self, entry: AudioMetadataEntry) -> Self {
+		self.with_payload(&AudioMetadata { entries: vec![entry] })
+	}
+
+	/// Sends an `audio.metadata` message containing a `WordBoundary`.
+	pub fn with_word_boundary(self, at_millis: u64, duration_millis: u64, text: &str) -> Self {
+		self.with_metadata_entry(AudioMetadataEntry::WordBoundary(BoundaryMetadata {
+			offset: at_millis * 10_000,
+			duration: duration_millis * 10_000,
+			text_offset: None,
+			text: BoundaryText {
+				text: text.to_string(),
+				length: Some(text.chars().count() as u32),
+				boundary_type: Some(BoundaryType::Word)
+			}
+		}))
+	}
+
+	/// Sends an `audio.metadata` message containing a `Bookmark`.
+	pub fn with_bookmark(self, at_millis: u64, mark: &str) -> Self {
+		self.with_metadata_entry(AudioMetadataEntry::Bookmark(BookmarkMetadata {
+			offset: at_millis * 10_000,
+			bookmark: mark.to_string()
+		}))
+	}
+
+	/// Sends a binary `audio` message.
+	pub fn with_audio(mut self, audio: impl Into<Vec<u8>>) -> Self {
+		self.steps.push(Step::Audio(audio.into()));
+		self
+	}
+
+	/// Sends a `turn.end` message.
+	pub fn with_turn_end(self) -> Self {
+		self.with_payload(&TurnEnd {})
+	}
+
+	/// Sends a JSON message with a typed payload.
+	pub fn with_payload<P: MessagePayload>(self, payload: &P) -> Self {
+		self.with_message(P::PATH, simd_json::to_string(payload).expect("payloads are always serializable"))
+	}
+
+	/// Sends a text message with an arbitrary path and body.
+	pub fn with_message(mut self, path: &'static str, body: impl Into<String>) -> Self {
+		self.steps.push(Step::Text { path, body: body.into() });
+		self
+	}
+
+	/// Closes the connection with the given close code & reason. No further steps are sent.
+	pub fn with_close(mut self, code: u16, reason: impl Into<String>) -> Self {
+		self.steps.push(Step::Close(code, reason.into()));
+		self
+	}
+
+	/// Waits before sending the next step.
+	pub fn with_delay(mut self, delay: Duration) -> Self {
+		self.steps.push(Step::Delay(delay));
+		self
+	}
+}
+
+/// A turn received by the [`MockServer`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct MockRequest {
+	/// The request ID of the turn.
+	pub request_id: String,
+	/// The requested output format, from the `synthesis.context`.
+	pub output_format: String,
+	/// The body of the `synthesis.context` message.
+	pub context: SynthesisContext,
+	/// The SSML document to synthesise.
+	pub ssml: String
+}
+
+#[derive(Default)]
+struct State {
+	turns: Mutex<VecDeque<MockTurn>>,
+	handshakes: Mutex<VecDeque<Handshake>>,
+	requests: Mutex<Vec<MockRequest>>,
+	speech_configs: Mutex<Vec<SpeechConfig>>,
+	protocol_errors: Mutex<Vec<String>>,
+	connections: AtomicUsize
+}
+
+/// A local WebSocket server which mocks the Azure text-to-speech service. See the [module docs](self).
+///
+/// The server shuts down, closing all of its connections, when dropped.
+pub struct MockServer {
+	addr: SocketAddr,
+	state: Arc<State>,
+	task: JoinHandle<()>
+}
+
+impl MockServer {
+	/// Starts a server listening on a random local port.
+	pub async fn start() -> std::io::Result<Self> {
+		let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+		let addr = listener.local_addr()?;
+		let state = Arc::new(State::default());
+		let task = tokio::spawn({
+			let state = Arc::clone(&state);
+			async move {
+				let mut connections = JoinSet::new();
+				while let Ok((stream, _)) = listener.accept().await {
+					connections.spawn(handle_connection(Arc::clone(&state), stream));
+				}
+			}
+		});
+		Ok(Self { addr, state, task })
+	}
+
+	/// Starts a server listening on a random local port, with `turns` already [queued](Self::push_turn).
+	pub async fn start_with(turns: impl IntoIterator<Item = MockTurn>) -> std::io::Result<Self> {
+		let server = Self::start().await?;
+		server.state.turns.lock().unwrap().extend(turns);
+		Ok(server)
+	}
+
+	/// Returns the `ws://` endpoint URL of this server.
+	pub fn endpoint(&self) -> String {
+		format!("ws://{}/cognitiveservices/websocket/v1", self.addr)
+	}
+
+	/// Returns a synthesiser builder configured to connect to this server.
+	pub fn synthesiser_builder(&self) -> AzureCognitiveSpeechServicesSynthesiserBuilder {
+		AzureCognitiveSpeechServicesSynthesiserBuilder::new()
+			.with_endpoint(self.endpoint())
+			.with_subscription_key("mock")
+	}
+
+	/// Queues the response to a future turn. Turns are answered in the order they were queued, across all connections;
+	/// once the queue is empty, turns are answered with [`MockTurn::default`].
+	pub fn push_turn(&self, turn: MockTurn) {
+		self.state.turns.lock().unwrap().push_back(turn);
+	}
+
+	/// Rejects the next connection's WebSocket upgrade request with the given HTTP status code.
+	pub fn reject_next_connection(&self, status: u16) {
+		self.state.handshakes.lock().unwrap().push_back(Handshake::Reject(status));
+	}
+
+	/// Waits before accepting the next connection's WebSocket upgrade request.
+	pub fn delay_next_connection(&self, delay: Duration) {
+		self.state.handshakes.lock().unwrap().push_back(Handshake::Delay(delay));
+	}
+
+	/// Returns all turns received so far.
+	pub fn requests(&self) -> Vec<MockRequest> {
+		self.state.requests.lock().unwrap().clone()
+	}
+
+	/// Returns the `speech.config` of each connection so far, in the order they were received.
+	pub fn speech_configs(&self) -> Vec<SpeechConfig> {
+		self.state.speech_configs.lock().unwrap().clone()
+	}
+
+	/// Returns the number of connections accepted so far, including rejected ones.
+	pub fn connections(&self) -> usize {
+		self.state.connections.load(Ordering::Relaxed)
+	}
+
+	/// Returns the protocol violations detected so far. The server closes the connection with code `1007` upon a
+	/// violation.
+	pub fn protocol_errors(&self) -> Vec<String> {
+		self.state.protocol_errors.lock().unwrap().clone()
+	}
+}
+
+impl Drop for MockServer {
+	fn drop(&mut self) {
+		self.task.abort();
+	}
+}
+
+async fn handle_connection(state: Arc<State>, mut stream: TcpStream) {
+	state.connections.fetch_add(1, Ordering::Relaxed);
+
+	let handshake = state.handshakes.lock().unwrap().pop_front();
+	if let Some(Handshake::Delay(delay)) = handshake {
+		tokio::time::sleep(delay).await;
+	} else if let Some(Handshake::Reject(status)) = handshake {
+		// Read the request head before responding, so the client doesn't see a reset connection instead.
+		let mut head = Vec::new();
+		let mut buf = [0; 1024];
+		while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+			match stream.read(&mut buf).await {
+				Ok(0) | Err(_) => return,
+				Ok(n) => head.extend_from_slice(&buf[..n])
+			}
+		}
+		let _ = stream
+			.write_all(format!("HTTP/1.1 {status} Mock Rejection\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").as_bytes())
+			.await;
+		return;
+	}
+
+	let Ok(mut websocket) = ServerBuilder::new().accept(stream).await else {
+		return;
+	};
+	if let Err(violation) = serve(&state, &mut websocket).await {
+		let _ = websocket.send(Message::close(CloseCode::try_from(1007).ok(), &violation)).await;
+		state.protocol_errors.lock().unwrap().push(violation);
+	}
+}
+
+/// Serves turns on a connection until the client disconnects, returning a description of the first protocol violation.
+async fn serve(state: &State, websocket: &mut WebSocketStream<TcpStream>) -> Result<(), String> {
+	let Some(config) = next_message(websocket).await? else {
+		return Ok(());
+	};
+	let config = config
+		.into_payload::<SpeechConfig>()
+		.map_err(|e| format!("invalid `speech.config`: {e}"))?;
+	state.speech_configs.lock().unwrap().push(config);
+
+	loop {
+		let Some(context) = next_message(websocket).await? else {
+			return Ok(());
+		};
+		let request_id = context.request_id().to_string();
+		let context = context
+			.into_payload::<SynthesisContext>()
+			.map_err(|e| format!("invalid `synthesis.context`: {e}"))?;
+		let output_format = context.synthesis.audio.output_format.clone();
+
+		let Some(ssml) = next_message(websocket).await? else {
+			return Err("connection closed between `synthesis.context` and `ssml`".to_string());
+		};
+		expect_path(&ssml, "ssml")?;
+		if ssml.request_id() != request_id {
+			return Err(format!("`ssml` request ID `{}` does not match `synthesis.context` request ID `{request_id}`", ssml.request_id()));
+		}
+		let ssml = ssml.into_body().into_text().ok_or("`ssml` must be a text message")?;
+		if !ssml.trim_start().starts_with("<speak") {
+			return Err("`ssml` body is not a `<speak>` document".to_string());
+		}
+
+		state.requests.lock().unwrap().push(MockRequest {
+			request_id: request_id.clone(),
+			output_format,
+			context,
+			ssml
+		});
+
+		let turn = state.turns.lock().unwrap().pop_front().unwrap_or_default();
+		for step in turn.steps {
+			let message = match step {
+				Step::Text { path, body } => AzureCognitiveSpeechServicesMessage::builder(path, &request_id)
+					.with_content_type(AzureCognitiveSpeechServicesMessage::CONTENT_TYPE_JSON)
+					.with_body(body),
+				Step::Audio(audio) => AzureCognitiveSpeechServicesMessage::builder("audio", &request_id)
+					.with_content_type("audio/x-wav")
+					.with_stream_id(STREAM_ID)
+					.with_body(audio),
+				Step::Close(code, reason) => {
+					let _ = websocket.send(Message::close(CloseCode::try_from(code).ok(), &reason)).await;
+					return Ok(());
+				}
+				Step::Delay(delay) => {
+					tokio::time::sleep(delay).await;
+					continue;
+				}
+			};
+			let message = message
+				.build()
+				.and_then(AzureCognitiveSpeechServicesMessage::into_websocket_message)
+				.expect("scripted mock messages must have valid headers");
+			if websocket.send(message).await.is_err() {
+				return Ok(());
+			}
+		}
+	}
+}
+
+/// Receives the next protocol message, or `None` if the client closed the connection.
+async fn next_message(websocket: &mut WebSocketStream<TcpStream>) -> Result<Option<AzureCognitiveSpeechServicesMessage>, String> {
+	while let Some(message) = websocket.next().await {
+		let Ok(message) = message else {
+			return Ok(None);
+		};
+		let message = if message.is_text() {
+			message.as_text().unwrap_or_default().parse()
+		} else if message.is_binary() {
+			AzureCognitiveSpeechServicesMessage::try_from(&*message.into_payload())
+		} else if message.is_close() {
+			return Ok(None);
+		} else {
+			continue;
+		};
+		return message.map(Some).map_err(|e| format!("malformed message: {e}"));
+	}
+	Ok(None)
+}
+
+fn expect_path(message: &AzureCognitiveSpeechServicesMessage, path: &str) -> Result<(), String> {
+	if message.path() == path {
+		Ok(())
+	} else {
+		Err(format!("expected `{path}` message, got `{}`", message.path()))
+	}
+}
+
+fn service_context() -> ServiceContext {
+	ServiceContext {
+		service_tag: Some("mock".to_string())
+	}
+}
+
+/// Synthesises a short text with `synthesiser` and collects its events, for the mock server tests of each module.
+#[cfg(test)]
+pub(crate) async fn synthesise(
+	synthesiser: &crate::AzureCognitiveSpeechServicesSynthesiser,
+	config: impl Into<crate::AzureUtteranceConfig>
+) -> crate::Result<Vec<crate::SynthesisEvent>> {
+	use futures_util::TryStreamExt;
+
+	let format = crate::AzureOutputFormat::Raw24Khz16BitMonoPcm.to_audio_format().unwrap();
+	synthesiser
+		.synthesise_text_events("Hello, world!", &format, config)
+		.await?
+		.try_collect()
+		.await
+}
+
+#[cfg(test)]
+mod tests {
+	use speech_synthesis::UtteranceConfig;
+
+	use super::*;
+	use crate::SynthesisEvent;
+
+	#[tokio::test]
+	async fn test_synthesise() -> crate::Result<()> {
+		let server = MockServer::start_with([MockTurn::new()
+			.with_turn_start()
+			.with_response()
+			.with_word_boundary(50, 300, "Hello")
+			.with_audio(vec![0; 4800])
+			.with_bookmark(400, "mark")
+			.with_audio(vec![0; 4800])
+			.with_turn_end()])
+		.await?;
+
+		let events = synthesise(&server.synthesiser_builder().build()?, &UtteranceConfig::default()).await?;
+		assert!(
+			matches!(&events[0], SynthesisEvent::WordBoundary { from_millis, to_millis, text, text_range } if *from_millis == 50. && *to_millis == 350. && &**text == "Hello" && *text_range == Some(0..5))
+		);
+		assert!(matches!(&events[1], SynthesisEvent::AudioChunk(audio) if audio.len() == 4800));
+		assert!(matches!(&events[2], SynthesisEvent::Bookmark { mark, .. } if &**mark == "mark"));
+		match &events[4] {
+			SynthesisEvent::Metrics(metrics) => {
+				assert_eq!(metrics.audio_bytes, 9600);
+				assert_eq!(metrics.audio_duration, Some(Duration::from_millis(200)));
+				assert!(metrics.connect.is_some() && metrics.turn_start.is_some() && metrics.first_audio.is_some());
+			}
+			e => panic!("expected metrics, got {e:?}")
+		}
+
+		let requests = server.requests();
+		assert_eq!(requests.len(), 1);
+		assert_eq!(requests[0].output_format, "raw-24khz-16bit-mono-pcm");
+		assert!(requests[0].ssml.contains("Hello, world!"));
+		assert!(server.protocol_errors().is_empty());
+		Ok(())
+	}
+}
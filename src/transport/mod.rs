@@ -0,0 +1,249 @@
+//! Transports carrying protocol messages between the synthesiser and the speech service.
+//!
+//! By default, the synthesiser connects with [`WebSocketTransport`]. A custom [`Transport`] can be configured with
+//! [`with_transport`](crate::AzureCognitiveSpeechServicesSynthesiserBuilder::with_transport) to e.g. tunnel through
+//! a custom network stack, use a different WebSocket implementation, or exchange messages over an in-memory channel in
+//! tests.
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures_util::{SinkExt, StreamExt, future::BoxFuture};
+use http::{HeaderName, HeaderValue, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_websockets::{ClientBuilder, Connector, WebSocketStream};
+
+pub use self::proxy::{Proxy, ProxyKind};
+use crate::{Error, message::AzureCognitiveSpeechServicesMessage};
+
+mod proxy;
+
+/// The parameters of a new connection to the speech service.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ConnectRequest {
+	/// The WebSocket endpoint URL to connect to.
+	pub uri: Uri,
+	/// The headers to send with the WebSocket upgrade request, including authentication headers.
+	pub headers: Vec<(HeaderName, HeaderValue)>
+}
+
+/// A frame received from the speech service.
+#[derive(Debug, Clone)]
+pub enum Frame {
+	/// A protocol message.
+	Message(AzureCognitiveSpeechServicesMessage),
+	/// The service closed the connection with the given close code & reason.
+	Close { code: u16, reason: String }
+}
+
+/// An open connection to the speech service, as returned by a [`Transport`].
+pub trait Channel: Send + 'static {
+	/// Sends a message to the service.
+	fn send(&mut self, message: AzureCognitiveSpeechServicesMessage) -> BoxFuture<'_, crate::Result<()>>;
+
+	/// Receives the next frame from the service, or `None` if the connection has been closed.
+	///
+	/// Implementations must be cancel safe, i.e. dropping the returned future before it completes must not lose a
+	/// frame; the synthesiser polls idle connections this way to check whether the service has closed them.
+	/// Control frames such as pings should be handled by the channel and not returned.
+	fn receive(&mut self) -> BoxFuture<'_, Option<crate::Result<Frame>>>;
+
+	/// Gracefully closes the connection.
+	fn close(&mut self) -> BoxFuture<'_, crate::Result<()>>;
+}
+
+/// Opens [`Channel`]s to the speech service.
+pub trait Transport: Send + Sync + 'static {
+	/// Opens a new connection as described by `request`.
+	fn connect(&self, request: ConnectRequest) -> BoxFuture<'_, crate::Result<Box<dyn Channel>>>;
+}
+
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+	fn connect(&self, request: ConnectRequest) -> BoxFuture<'_, crate::Result<Box<dyn Channel>>> {
+		(**self).connect(request)
+	}
+}
+
+/// The default [`Transport`], which connects to the service with
+/// [`tokio-websockets`](https://crates.io/crates/tokio-websockets), either directly or through a [`Proxy`].
+#[derive(Debug, Default)]
+pub struct WebSocketTransport {
+	connector: Option<Connector>,
+	proxy: Option<Proxy>
+}
+
+impl WebSocketTransport {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Configures the TLS connector used for `wss://` endpoints, e.g. to use a custom root certificate store.
+	pub fn with_connector(mut self, connector: Connector) -> Self {
+		self.connector = Some(connector);
+		self
+	}
+
+	/// Configures a proxy to tunnel connections through.
+	pub fn with_proxy(mut self, proxy: Proxy) -> Self {
+		self.proxy = Some(proxy);
+		self
+	}
+
+	fn client(&self, request: ConnectRequest) -> ClientBuilder<'_> {
+		let mut builder = ClientBuilder::from_uri(request.uri);
+		if let Some(connector) = &self.connector {
+			builder = builder.connector(connector);
+		}
+		for (name, value) in request.headers {
+			builder = builder.add_header(name, value);
+		}
+		builder
+	}
+
+	/// Performs the WebSocket handshake on an already established stream, e.g. a tunnel through a proxy. TLS must
+	/// already have been negotiated on the stream for `wss://` endpoints.
+	pub async fn connect_on<S>(&self, request: ConnectRequest, stream: S) -> crate::Result<Box<dyn Channel>>
+	where
+		S: AsyncRead + AsyncWrite + Send + Unpin + 'static
+	{
+		let (websocket, _response) = self.client(request).connect_on(stream).await?;
+		Ok(Box::new(WebSocketChannel(websocket)))
+	}
+}
+
+impl Transport for WebSocketTransport {
+	fn connect(&self, request: ConnectRequest) -> BoxFuture<'_, crate::Result<Box<dyn Channel>>> {
+		Box::pin(async move {
+			let host = request.uri.host().unwrap_or_default();
+			let Some(proxy) = self.proxy.as_ref().filter(|proxy| proxy.applies_to(host)) else {
+				let (websocket, _response) = self.client(request).connect().await?;
+				return Ok(Box::new(WebSocketChannel(websocket)) as Box<dyn Channel>);
+			};
+
+			let secure = match request.uri.scheme_str() {
+				Some("wss") => true,
+				Some("ws") => false,
+				_ => return Err(Error::InvalidEndpoint(format!("`{}`: scheme must be `wss` or `ws`", request.uri)))
+			};
+			let port = request.uri.port_u16().unwrap_or(if secure { 443 } else { 80 });
+			let host = host.trim_start_matches('[').trim_end_matches(']').to_string();
+			let stream = proxy.connect(&host, port).await?;
+			let stream = match (&self.connector, secure) {
+				(Some(connector), true) => connector.wrap(&host, stream).await?,
+				(None, true) => Connector::new()?.wrap(&host, stream).await?,
+				(_, false) => Connector::Plain.wrap(&host, stream).await?
+			};
+			self.connect_on(request, stream).await
+		})
+	}
+}
+
+struct WebSocketChannel<S>(WebSocketStream<S>);
+
+impl<S: AsyncRead + AsyncWrite + Send + Unpin + 'static> Channel for WebSocketChannel<S> {
+	fn send(&mut self, message: AzureCognitiveSpeechServicesMessage) -> BoxFuture<'_, crate::Result<()>> {
+		Box::pin(async move { Ok(self.0.send(message.into_websocket_message()?).await?) })
+	}
+
+	fn receive(&mut self) -> BoxFuture<'_, Option<crate::Result<Frame>>> {
+		Box::pin(async move {
+			loop {
+				let msg = match self.0.next().await? {
+					Ok(msg) => msg,
+					Err(e) => return Some(Err(e.into()))
+				};
+				let msg = if msg.is_binary() {
+					AzureCognitiveSpeechServicesMessage::try_from(Bytes::from(msg.into_payload()))
+				} else if msg.is_text() {
+					msg.as_text().unwrap_or_default().parse()
+				} else if let Some((code, reason)) = msg.as_close() {
+					return Some(Ok(Frame::Close {
+						code: code.into(),
+						reason: reason.to_string()
+					}));
+				} else {
+					continue;
+				};
+				return Some(msg.map(Frame::Message).map_err(Into::into));
+			}
+		})
+	}
+
+	fn close(&mut self) -> BoxFuture<'_, crate::Result<()>> {
+		Box::pin(async move { Ok(self.0.close().await?) })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::VecDeque;
+
+	use futures_util::TryStreamExt;
+	use speech_synthesis::UtteranceConfig;
+
+	use super::*;
+	use crate::{AzureCognitiveSpeechServicesSynthesiser, AzureOutputFormat, SynthesisEvent, message::AzureCognitiveSpeechServicesMessageBody};
+
+	/// A channel which answers every `ssml` message with a scripted turn, without any I/O.
+	#[derive(Default)]
+	struct InMemoryChannel {
+		replies: VecDeque<Frame>
+	}
+
+	impl Channel for InMemoryChannel {
+		fn send(&mut self, message: AzureCognitiveSpeechServicesMessage) -> BoxFuture<'_, crate::Result<()>> {
+			Box::pin(async move {
+				if message.path() == "ssml" {
+					let replies: [(_, AzureCognitiveSpeechServicesMessageBody); 3] =
+						[("turn.start", "{}".into()), ("audio", vec![0; 320].into()), ("turn.end", "{}".into())];
+					for (path, body) in replies {
+						let reply = AzureCognitiveSpeechServicesMessage::builder(path, message.request_id()).with_body(body);
+						self.replies.push_back(Frame::Message(reply.build()?));
+					}
+				}
+				Ok(())
+			})
+		}
+
+		fn receive(&mut self) -> BoxFuture<'_, Option<crate::Result<Frame>>> {
+			Box::pin(async move {
+				match self.replies.pop_front() {
+					Some(frame) => Some(Ok(frame)),
+					None => std::future::pending().await
+				}
+			})
+		}
+
+		fn close(&mut self) -> BoxFuture<'_, crate::Result<()>> {
+			Box::pin(async { Ok(()) })
+		}
+	}
+
+	struct InMemoryTransport;
+
+	impl Transport for InMemoryTransport {
+		fn connect(&self, request: ConnectRequest) -> BoxFuture<'_, crate::Result<Box<dyn Channel>>> {
+			assert!(request.headers.iter().any(|(name, _)| name == "ocp-apim-subscription-key"));
+			Box::pin(async { Ok(Box::new(InMemoryChannel::default()) as Box<dyn Channel>) })
+		}
+	}
+
+	#[tokio::test]
+	async fn test_custom_transport() -> crate::Result<()> {
+		let synthesiser = AzureCognitiveSpeechServicesSynthesiser::builder()
+			.with_region("westus2")
+			.with_subscription_key("key")
+			.with_transport(InMemoryTransport)
+			.build()?;
+		let format = AzureOutputFormat::Raw16Khz16BitMonoPcm.to_audio_format().unwrap();
+		let events: Vec<_> = synthesiser
+			.synthesise_text_events("Hello", &format, &UtteranceConfig::default())
+			.await?
+			.try_collect()
+			.await?;
+		assert!(matches!(&events[0], SynthesisEvent::AudioChunk(audio) if audio.len() == 320));
+		assert!(matches!(&events[1], SynthesisEvent::Metrics(metrics) if metrics.audio_duration == Some(std::time::Duration::from_millis(10))));
+		Ok(())
+	}
+}